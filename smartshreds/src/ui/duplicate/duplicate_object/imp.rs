@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{OnceCell, RefCell};
 
 use glib::Properties;
 use gtk::glib;
@@ -6,6 +6,7 @@ use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 
 use super::Duplicate;
+use crate::utils::duplicates::{DupFile, DuplicateFilterMode};
 
 #[derive(Properties, Default)]
 #[properties(wrapper_type = super::DuplicateObject)]
@@ -16,7 +17,15 @@ pub struct DuplicateObject {
     #[property(name = "path", get, set, type = String, member = path)]
     #[property(name = "date-created", get, set, type = String, member = date_created)]
     #[property(name = "background-color", get, set, type = String, member = background_color)]
+    #[property(name = "group", get, set, type = String, member = group)]
+    #[property(name = "kind", get, set, type = String, member = kind)]
+    #[property(name = "similarity", get, set, type = f64, member = similarity)]
+    #[property(name = "is-directory", get, set, type = bool, member = is_directory)]
     pub data: RefCell<Duplicate>,
+    /// The scanned file behind the row, `None` for the contents of a directory.
+    pub dup_file: RefCell<Option<DupFile>>,
+    /// The filter the file falls under, detected the first time it is filtered.
+    pub filter_mode: OnceCell<DuplicateFilterMode>,
 }
 
 #[glib::object_subclass]
@@ -1,14 +1,29 @@
 mod imp;
 
+use std::fs;
+use std::path::Path;
+
 use glib::Object;
-use gtk::glib;
+use gtk::subclass::prelude::*;
+use gtk::{gio, glib};
+
+use crate::utils::duplicates::{DupFile, DuplicateFilterMode};
+use crate::utils::format_size;
 
 glib::wrapper! {
     pub struct DuplicateObject(ObjectSubclass<imp::DuplicateObject>);
 }
 
 impl DuplicateObject {
-    pub fn new(name: String, size: String, path: String, date_created: String, bgcolor: String) -> Self {
+    pub fn new(
+        name: String,
+        size: String,
+        path: String,
+        date_created: String,
+        bgcolor: String,
+        group: String,
+        similarity: f64,
+    ) -> Self {
         Object::builder()
             .property("name", &name)
             .property("size", &size)
@@ -16,8 +31,71 @@ impl DuplicateObject {
             .property("date-created", &date_created)
             .property("check", &false)
             .property("background-color", &bgcolor)
+            .property("group", &group)
+            .property("similarity", similarity)
             .build()
     }
+
+    pub fn dup_file(&self) -> Option<DupFile> {
+        self.imp().dup_file.borrow().clone()
+    }
+
+    pub fn set_dup_file(&self, dup_file: DupFile) {
+        self.imp().dup_file.replace(Some(dup_file));
+    }
+
+    pub fn filter_mode(&self) -> DuplicateFilterMode {
+        self.imp()
+            .filter_mode
+            .get_or_init(|| DuplicateFilterMode::of(Path::new(&self.path())))
+            .clone()
+    }
+
+    /// The entries of a duplicate directory, directories first, or `None` for a file.
+    pub fn contents(&self) -> Option<gio::ListStore> {
+        if !self.is_directory() {
+            return None;
+        }
+
+        let mut entries: Vec<(bool, fs::DirEntry)> = match fs::read_dir(self.path()) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| (entry.file_type().is_ok_and(|t| t.is_dir()), entry))
+                .collect(),
+            Err(e) => {
+                eprintln!("Error reading directory {}: {}", self.path(), e);
+                return None;
+            }
+        };
+        entries.sort_by(|(a_is_dir, a), (b_is_dir, b)| {
+            b_is_dir
+                .cmp(a_is_dir)
+                .then_with(|| a.file_name().cmp(&b.file_name()))
+        });
+
+        let store = gio::ListStore::new::<DuplicateObject>();
+        for (is_dir, entry) in entries {
+            let size = if is_dir {
+                let count = fs::read_dir(entry.path()).map_or(0, |entries| entries.count());
+                format!("{} items", count)
+            } else {
+                format_size(entry.metadata().map_or(0, |metadata| metadata.len()))
+            };
+            let child = DuplicateObject::new(
+                entry.file_name().to_string_lossy().to_string(),
+                size,
+                entry.path().to_string_lossy().to_string(),
+                String::new(),
+                self.background_color(),
+                self.group(),
+                self.similarity(),
+            );
+            child.set_kind(self.kind());
+            child.set_is_directory(is_dir);
+            store.append(&child);
+        }
+        Some(store)
+    }
 }
 
 #[derive(Default, Clone, Debug)]
@@ -28,4 +106,12 @@ pub struct Duplicate {
     pub path: String,
     pub date_created: String,
     pub background_color: String,
+    /// Identifies the group of duplicates the file belongs to.
+    pub group: String,
+    /// What makes the files of the group alike, e.g. "Similar documents".
+    pub kind: String,
+    /// How close the file is to the first file of its group, 1 for exact copies.
+    pub similarity: f64,
+    /// Whether the row is a duplicate directory that can be expanded.
+    pub is_directory: bool,
 }
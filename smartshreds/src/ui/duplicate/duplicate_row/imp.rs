@@ -18,6 +18,8 @@ pub struct DuplicateRow {
     pub date_created: TemplateChild<Label>,
     #[template_child]
     pub path: TemplateChild<Label>,
+    #[template_child]
+    pub similarity: TemplateChild<Label>,
     pub bindings: RefCell<Vec<Binding>>,
 }
 
@@ -22,6 +22,7 @@ impl DuplicateRow {
         let size = self.imp().size.get();
         let path = self.imp().path.get();
         let date_created = self.imp().date_created.get();
+        let similarity = self.imp().similarity.get();
         let mut bindings = self.imp().bindings.borrow_mut();
 
         let check_binding = duplicate_object
@@ -55,6 +56,22 @@ impl DuplicateRow {
             .build();
         bindings.push(date_created_binding);
 
+        let similarity_binding = duplicate_object
+            .bind_property("similarity", &similarity, "label")
+            .transform_to(|_, similarity: f64| Some(format!("{:.0}%", similarity * 100.0)))
+            .sync_create()
+            .build();
+        bindings.push(similarity_binding);
+
+        let kind_binding = duplicate_object
+            .bind_property("kind", &similarity, "tooltip-text")
+            .sync_create()
+            .build();
+        bindings.push(kind_binding);
+
+        // the contents of a duplicate directory are only there to be looked at
+        check.set_visible(duplicate_object.dup_file().is_some());
+
         self.set_property("name", duplicate_object.background_color().as_str());
     }
 
@@ -0,0 +1,42 @@
+use std::cell::RefCell;
+
+use adw::subclass::prelude::*;
+use gtk::{glib, Box, Button, CompositeTemplate, Label};
+
+use crate::utils::junk::JunkFile;
+
+#[derive(Default, CompositeTemplate)]
+#[template(resource = "/org/gtk_rs/SmartShreds/junk_box.ui")]
+pub struct JunkBox {
+    #[template_child]
+    pub junk_count_label: TemplateChild<Label>,
+    #[template_child]
+    pub categories_box: TemplateChild<Box>,
+    #[template_child]
+    pub size_label: TemplateChild<Label>,
+    #[template_child]
+    pub clean_button: TemplateChild<Button>,
+    /// The junk shown, cleaned up by `clean_button`.
+    pub junk_files: RefCell<Vec<JunkFile>>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for JunkBox {
+    const NAME: &'static str = "JunkBox";
+    type Type = super::JunkBox;
+    type ParentType = Box;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.bind_template();
+    }
+
+    fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+        obj.init_template();
+    }
+}
+
+impl BoxImpl for JunkBox {}
+
+impl ObjectImpl for JunkBox {}
+
+impl WidgetImpl for JunkBox {}
@@ -0,0 +1,15 @@
+mod imp;
+
+use gtk::glib::{self, Object};
+
+glib::wrapper! {
+    pub struct JunkBox(ObjectSubclass<imp::JunkBox>)
+        @extends gtk::Box, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::Orientable;
+}
+
+impl JunkBox {
+    pub fn new() -> Self {
+        Object::builder().build()
+    }
+}
@@ -0,0 +1,46 @@
+use std::cell::RefCell;
+
+use adw::subclass::prelude::*;
+use gtk::{glib, Box, Button, CheckButton, CompositeTemplate, Label, ListBox};
+
+use crate::utils::largest::LargeEntry;
+
+#[derive(Default, CompositeTemplate)]
+#[template(resource = "/org/gtk_rs/SmartShreds/largest_box.ui")]
+pub struct LargestBox {
+    #[template_child]
+    pub threshold_label: TemplateChild<Label>,
+    #[template_child]
+    pub files_list: TemplateChild<ListBox>,
+    #[template_child]
+    pub folders_list: TemplateChild<ListBox>,
+    #[template_child]
+    pub selected_label: TemplateChild<Label>,
+    #[template_child]
+    pub move_button: TemplateChild<Button>,
+    #[template_child]
+    pub trash_button: TemplateChild<Button>,
+    /// The entries listed and the check marking each of them.
+    pub rows: RefCell<Vec<(CheckButton, LargeEntry)>>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for LargestBox {
+    const NAME: &'static str = "LargestBox";
+    type Type = super::LargestBox;
+    type ParentType = Box;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.bind_template();
+    }
+
+    fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+        obj.init_template();
+    }
+}
+
+impl BoxImpl for LargestBox {}
+
+impl ObjectImpl for LargestBox {}
+
+impl WidgetImpl for LargestBox {}
@@ -1,3 +1,7 @@
 pub mod file_type_box;
+pub mod junk_box;
+pub mod largest_box;
 pub mod recents_box;
-pub mod duplicate;
\ No newline at end of file
+pub mod duplicate;
+pub mod stale_box;
+pub mod storage_analysis_plot;
\ No newline at end of file
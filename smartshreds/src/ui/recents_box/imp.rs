@@ -1,10 +1,30 @@
 use adw::subclass::prelude::*;
-use gtk::{CompositeTemplate, glib};
+use gtk::{CompositeTemplate, DropDown, ListBox, glib};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::utils::recents::ActionEvent;
 
 
 #[derive(CompositeTemplate, Default)]
 #[template(resource = "/org/gtk_rs/SmartShreds/recents_box.ui")]
-pub struct RecentsBox {}
+pub struct RecentsBox {
+    #[template_child]
+    pub kind_filter: TemplateChild<DropDown>,
+    #[template_child]
+    pub node_filter: TemplateChild<DropDown>,
+    /// What happened in the watched folders, latest first.
+    #[template_child]
+    pub feed_list: TemplateChild<ListBox>,
+    /// The actions of the rows of `feed_list`, in the same order.
+    pub feed: RefCell<VecDeque<ActionEvent>>,
+    /// The period the timeline goes back.
+    #[template_child]
+    pub timeline_period: TemplateChild<DropDown>,
+    /// The recorded actions grouped by day.
+    #[template_child]
+    pub timeline_box: TemplateChild<gtk::Box>,
+}
 
 #[glib::object_subclass]
 impl ObjectSubclass for RecentsBox {
@@ -4,7 +4,7 @@ use gtk::glib::{self, Object};
 
 glib::wrapper! {
     pub struct RecentsBox(ObjectSubclass<imp::RecentsBox>) 
-        @extends gtk::Notebook, gtk::Widget,
+        @extends gtk::Box, gtk::Widget,
         @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::Orientable;
 }
 
@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+
+use adw::subclass::prelude::*;
+use gtk::{glib, Box, Button, CheckButton, CompositeTemplate, Label};
+
+use crate::utils::stale::{StaleFile, StaleReport};
+
+#[derive(Default, CompositeTemplate)]
+#[template(resource = "/org/gtk_rs/SmartShreds/stale_box.ui")]
+pub struct StaleBox {
+    #[template_child]
+    pub summary_label: TemplateChild<Label>,
+    #[template_child]
+    pub buckets_box: TemplateChild<Box>,
+    #[template_child]
+    pub selected_label: TemplateChild<Label>,
+    #[template_child]
+    pub archive_button: TemplateChild<Button>,
+    #[template_child]
+    pub trash_button: TemplateChild<Button>,
+    pub report: RefCell<StaleReport>,
+    /// The checks of the buckets and of the files listed, with the files each one marks.
+    pub checks: RefCell<Vec<(CheckButton, Vec<StaleFile>)>>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for StaleBox {
+    const NAME: &'static str = "StaleBox";
+    type Type = super::StaleBox;
+    type ParentType = Box;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.bind_template();
+    }
+
+    fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+        obj.init_template();
+    }
+}
+
+impl BoxImpl for StaleBox {}
+
+impl ObjectImpl for StaleBox {}
+
+impl WidgetImpl for StaleBox {}
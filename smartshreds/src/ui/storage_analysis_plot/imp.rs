@@ -0,0 +1,43 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+use adw::subclass::prelude::*;
+use gtk::glib::{self, Properties};
+use gtk::{graphene, prelude::*};
+
+use super::Tile;
+use crate::utils::dir_tree::DirTree;
+
+#[derive(Default, Properties)]
+#[properties(wrapper_type = super::StorageAnalysisPlot)]
+pub struct StorageAnalysisPlot {
+    pub tree: RefCell<DirTree>,
+    /// The folder whose subfolders are shown, `None` for the analysed folders.
+    pub current: RefCell<Option<PathBuf>>,
+    /// The tiles of the last drawing, outermost first.
+    pub tiles: RefCell<Vec<Tile>>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for StorageAnalysisPlot {
+    const NAME: &'static str = "StorageAnalysisPlot";
+    type Type = super::StorageAnalysisPlot;
+    type ParentType = gtk::Widget;
+}
+
+#[glib::derived_properties]
+impl ObjectImpl for StorageAnalysisPlot {
+    fn constructed(&self) {
+        self.parent_constructed();
+        self.obj().setup_gestures();
+    }
+}
+
+impl WidgetImpl for StorageAnalysisPlot {
+    fn snapshot(&self, snapshot: &gtk::Snapshot) {
+        let plot = self.obj();
+        let (width, height) = (plot.width() as f32, plot.height() as f32);
+        let cr = snapshot.append_cairo(&graphene::Rect::new(0.0, 0.0, width, height));
+        plot.draw(&cr, width as f64, height as f64);
+    }
+}
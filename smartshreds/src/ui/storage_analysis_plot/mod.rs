@@ -0,0 +1,259 @@
+mod imp;
+
+use std::path::{Path, PathBuf};
+
+use adw::subclass::prelude::*;
+use gtk::glib::{self, clone, Object};
+use gtk::{cairo, gdk, prelude::*};
+
+use crate::utils::dir_tree::{squarify, DirTree, Rect};
+use crate::utils::format_size;
+
+/// Height of the line naming the folder shown.
+const HEADER_HEIGHT: f64 = 28.0;
+/// Height of the name of a folder, above its subfolders.
+const LABEL_HEIGHT: f64 = 18.0;
+/// Colours of the folders, the same as the file type cards.
+const PALETTE: [(f64, f64, f64); 6] = [
+    (0.275, 0.510, 0.706),
+    (0.0, 0.502, 0.502),
+    (0.576, 0.439, 0.859),
+    (1.0, 0.388, 0.278),
+    (1.0, 0.647, 0.0),
+    (0.439, 0.502, 0.565),
+];
+
+glib::wrapper! {
+    pub struct StorageAnalysisPlot(ObjectSubclass<imp::StorageAnalysisPlot>)
+        @extends gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+/// A rectangle of the treemap and the folder it stands for.
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub rect: Rect,
+    /// `None` for the files directly in a folder.
+    pub path: Option<PathBuf>,
+    pub name: String,
+    pub size: u64,
+    /// The folder to show when the tile is clicked.
+    pub target: Option<PathBuf>,
+}
+
+impl StorageAnalysisPlot {
+    pub fn new() -> Self {
+        Object::builder().build()
+    }
+
+    /// Show the sizes of `tree`, starting from the analysed folders.
+    pub fn set_tree(&self, tree: DirTree) {
+        self.imp().tree.replace(tree);
+        self.imp().current.replace(None);
+        self.queue_draw();
+    }
+
+    /// Show the subfolders of `dir`, if it has any.
+    pub fn drill_down(&self, dir: PathBuf) {
+        if self.imp().tree.borrow().entries(Some(&dir)).is_empty() {
+            return;
+        }
+        self.imp().current.replace(Some(dir));
+        self.queue_draw();
+    }
+
+    /// Go back to the folder above the one shown.
+    pub fn zoom_out(&self) {
+        let parent = match self.imp().current.borrow().as_deref() {
+            Some(dir) => self.imp().tree.borrow().parent(dir),
+            None => return,
+        };
+        self.imp().current.replace(parent);
+        self.queue_draw();
+    }
+
+    fn tile_at(&self, x: f64, y: f64) -> Option<Tile> {
+        self.imp()
+            .tiles
+            .borrow()
+            .iter()
+            .rev()
+            .find(|tile| tile.rect.contains(x, y))
+            .cloned()
+    }
+
+    fn setup_gestures(&self) {
+        let click = gtk::GestureClick::new();
+        click.set_button(0);
+        click.connect_released(clone!(
+            #[weak(rename_to = plot)]
+            self,
+            move |gesture, _, x, y| {
+                if gesture.current_button() == gdk::BUTTON_SECONDARY || y < HEADER_HEIGHT {
+                    plot.zoom_out();
+                } else if let Some(dir) = plot.tile_at(x, y).and_then(|tile| tile.target) {
+                    plot.drill_down(dir);
+                }
+            }
+        ));
+        self.add_controller(click);
+
+        self.set_has_tooltip(true);
+        self.connect_query_tooltip(|plot, x, y, _, tooltip| {
+            let Some(tile) = plot.tile_at(x as f64, y as f64) else {
+                return false;
+            };
+            let name = tile
+                .path
+                .map_or(tile.name, |path| path.display().to_string());
+            tooltip.set_text(Some(&format!("{}\n{}", name, format_size(tile.size))));
+            true
+        });
+    }
+
+    /// Draw the folder shown as a treemap, each folder holding its own subfolders.
+    fn draw(&self, cr: &cairo::Context, width: f64, height: f64) {
+        let tree = self.imp().tree.borrow();
+        let current = self.imp().current.borrow().clone();
+        let mut tiles = Vec::new();
+        cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+        cr.set_font_size(12.0);
+
+        let title = match &current {
+            Some(dir) => format!(
+                "{}  ·  {}  ·  right-click to go back",
+                dir.display(),
+                format_size(tree.size(Some(dir)))
+            ),
+            None => format!("Analysed folders  ·  {}", format_size(tree.size(None))),
+        };
+        let foreground = self.color();
+        cr.set_source_rgba(
+            foreground.red() as f64,
+            foreground.green() as f64,
+            foreground.blue() as f64,
+            foreground.alpha() as f64,
+        );
+        draw_label(
+            cr,
+            Rect::new(0.0, 0.0, width, HEADER_HEIGHT),
+            &title,
+            4.0,
+            18.0,
+        );
+
+        let area = Rect::new(0.0, HEADER_HEIGHT, width, height - HEADER_HEIGHT);
+        let items = items_of(&tree, current.as_deref());
+        let sizes: Vec<u64> = items.iter().map(|(_, size)| *size).collect();
+        for (i, ((path, size), rect)) in items.iter().zip(squarify(&sizes, area)).enumerate() {
+            let rect = rect.inset(1.0);
+            let (red, green, blue) = PALETTE[i % PALETTE.len()];
+            cr.set_source_rgb(red, green, blue);
+            cr.rectangle(rect.x, rect.y, rect.width, rect.height);
+            let _ = cr.fill();
+
+            let name = tile_name(path.as_deref());
+            cr.set_source_rgb(1.0, 1.0, 1.0);
+            let label = format!("{}  {}", name, format_size(*size));
+            draw_label(cr, rect, &label, 4.0, 13.0);
+            tiles.push(Tile {
+                rect,
+                path: path.clone(),
+                name,
+                size: *size,
+                target: path.clone(),
+            });
+
+            // one level of subfolders inside each folder
+            let Some(path) = path else {
+                continue;
+            };
+            let inner = Rect::new(
+                rect.x + 2.0,
+                rect.y + LABEL_HEIGHT,
+                rect.width - 4.0,
+                rect.height - LABEL_HEIGHT - 2.0,
+            );
+            let children = items_of(&tree, Some(path));
+            if inner.width < 24.0
+                || inner.height < 24.0
+                || children.iter().all(|(p, _)| p.is_none())
+            {
+                continue;
+            }
+            let sizes: Vec<u64> = children.iter().map(|(_, size)| *size).collect();
+            for ((child, size), child_rect) in children.iter().zip(squarify(&sizes, inner)) {
+                cr.set_source_rgba(1.0, 1.0, 1.0, if child.is_some() { 0.25 } else { 0.1 });
+                cr.rectangle(
+                    child_rect.x,
+                    child_rect.y,
+                    child_rect.width,
+                    child_rect.height,
+                );
+                let _ = cr.fill_preserve();
+                cr.set_source_rgb(red, green, blue);
+                cr.set_line_width(1.0);
+                let _ = cr.stroke();
+
+                let name = tile_name(child.as_deref());
+                cr.set_source_rgb(1.0, 1.0, 1.0);
+                draw_label(cr, child_rect, &name, 3.0, 12.0);
+                tiles.push(Tile {
+                    rect: child_rect,
+                    path: child.clone(),
+                    name,
+                    size: *size,
+                    target: Some(path.clone()),
+                });
+            }
+        }
+
+        if items.is_empty() {
+            cr.set_source_rgba(
+                foreground.red() as f64,
+                foreground.green() as f64,
+                foreground.blue() as f64,
+                0.6,
+            );
+            draw_label(cr, area, "No files analysed yet", 4.0, 18.0);
+        }
+        self.imp().tiles.replace(tiles);
+    }
+}
+
+/// The subfolders of `dir` and the files directly in it, largest first.
+fn items_of(tree: &DirTree, dir: Option<&Path>) -> Vec<(Option<PathBuf>, u64)> {
+    let mut items: Vec<(Option<PathBuf>, u64)> = tree
+        .entries(dir)
+        .into_iter()
+        .map(|(path, size)| (Some(path), size))
+        .collect();
+    let loose_size = dir.map_or(0, |dir| tree.loose_size(dir));
+    if loose_size > 0 {
+        items.push((None, loose_size));
+        items.sort_by(|(_, a), (_, b)| b.cmp(a));
+    }
+    items
+}
+
+fn tile_name(path: Option<&Path>) -> String {
+    match path {
+        Some(path) => path.file_name().map_or(path.display().to_string(), |name| {
+            name.to_string_lossy().to_string()
+        }),
+        None => "Files".to_string(),
+    }
+}
+
+/// Write `text` at the top left of `rect`, cut where the rectangle ends.
+fn draw_label(cr: &cairo::Context, rect: Rect, text: &str, x: f64, y: f64) {
+    if rect.width < 2.0 * x || rect.height < y {
+        return;
+    }
+    let _ = cr.save();
+    cr.rectangle(rect.x, rect.y, rect.width - x, rect.height);
+    cr.clip();
+    cr.move_to(rect.x + x, rect.y + y);
+    let _ = cr.show_text(text);
+    let _ = cr.restore();
+}
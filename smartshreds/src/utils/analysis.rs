@@ -3,8 +3,16 @@ use chrono::{DateTime, Utc};
 // use reqwest::Client;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
-use std::path::PathBuf;
-use sysinfo::System;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use super::dir_tree::DirTree;
+use super::disks::DiskUsage;
+use super::junk::{JunkDetector, JunkFile, JunkPlaces};
+use super::largest::{LargeEntry, Largest};
+use super::stale::StaleReport;
+use super::mime_type::{self, ContentKind};
+use super::recents::{Action, Change};
 // use serde::{Deserialize, Serialize};
 // use serde_json::json;
 // use super::runtime;
@@ -13,15 +21,93 @@ use sysinfo::System;
 pub struct StorageAnalysis {
     // size, count
     pub file_types_info: HashMap<FileType, (u64, u64)>,
-    pub inactive_files: Vec<PathBuf>,
-    pub junk_files: Vec<JunkFiles>,
+    /// Size of every analysed folder.
+    pub dir_tree: DirTree,
+    /// Files unused for a while, by how long.
+    pub stale_files: StaleReport,
+    pub junk_files: Vec<JunkFile>,
+    pub largest_files: Largest,
+    /// The largest folders below the analysed ones.
+    pub largest_dirs: Largest,
     pub memory_usage: MemoryUsage,
-    pub total_device_memory: u64,
+    /// The filesystems the analysed files are on.
+    pub disks: Vec<DiskUsage>,
+    /// Devices holding files of each type.
+    pub file_type_devices: HashMap<FileType, HashSet<u64>>,
     pub recent_files: Vec<PathBuf>,
+    /// Type and size of every analysed file, to take it back out when it changes.
+    pub files: HashMap<PathBuf, (FileType, u64)>,
+    /// Every analysed folder, to take the files in one out with it.
+    pub folders: HashSet<PathBuf>,
+}
+
+/// What an action of the file watcher changes in a storage analysis. It is read
+/// from the disk on its own, so the analysis only has to be locked to merge it.
+#[derive(Debug, Default)]
+pub struct AnalysisDelta {
+    /// A file, or a folder and everything in it, to take out first.
+    forgotten: Option<PathBuf>,
+    /// Folders found at and below the added path.
+    folders: Vec<PathBuf>,
+    /// Files found at and below the added path, with their type.
+    files: Vec<(PathBuf, FileType, fs::Metadata)>,
+    /// A file read again, no longer stale.
+    accessed: Option<PathBuf>,
+}
+
+impl AnalysisDelta {
+    /// Read what `action` changed on the disk. Folder sizes and the largest
+    /// entries are left as they were analysed.
+    pub fn of(action: &Action, path: &Path) -> Self {
+        match action {
+            Action::Create { .. } => Self::replacing(path, path),
+            // only a new content changes the type or size of a file, and a folder is
+            // modified when its entries are, each of them comes on its own
+            Action::Modify(Change::Content | Change::Size | Change::WriteTime | Change::Any)
+                if !path.is_dir() =>
+            {
+                Self::replacing(path, path)
+            }
+            // the other changes of metadata are known without reading the disk
+            Action::Modify(Change::AccessTime) => AnalysisDelta {
+                accessed: Some(path.to_path_buf()),
+                ..Self::default()
+            },
+            Action::Remove { .. } => AnalysisDelta {
+                forgotten: Some(path.to_path_buf()),
+                ..Self::default()
+            },
+            Action::Rename { from, to } | Action::Move { from, to } => Self::replacing(from, to),
+            Action::Modify(_) | Action::Access => Self::default(),
+        }
+    }
+
+    /// Take `forgotten` out, then add a new file, or a folder and everything in it.
+    fn replacing(forgotten: &Path, added: &Path) -> Self {
+        let mut delta = AnalysisDelta {
+            forgotten: Some(forgotten.to_path_buf()),
+            ..Self::default()
+        };
+        let mut dir_queue = VecDeque::from([added.to_path_buf()]);
+        while let Some(entry) = dir_queue.pop_front() {
+            if entry.is_dir() {
+                match fs::read_dir(&entry) {
+                    Ok(entries) => dir_queue.extend(entries.flatten().map(|e| e.path())),
+                    Err(e) => eprintln!("Error reading directory {:?}: {}", entry, e),
+                }
+                delta.folders.push(entry);
+            } else if let Ok(metadata) = fs::metadata(&entry) {
+                let file_type = FileType::of(&entry);
+                delta.files.push((entry, file_type, metadata));
+            }
+        }
+        delta
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct MemoryUsage {
+    /// Total size of the analysed files.
     pub size: u64,
     pub total_folders: u64,
     pub total_files: u64,
@@ -54,8 +140,7 @@ impl Default for FileType {
     }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum JunkFiles {
     RandomExe,
     DeletedFiles,
@@ -72,21 +157,58 @@ impl Default for JunkFiles {
     }
 }
 
-impl From<&str> for FileType {
-    fn from(value: &str) -> Self {
+impl From<&JunkFiles> for &str {
+    fn from(value: &JunkFiles) -> Self {
         match value {
-            "txt" | "doc" | "docx" | "pdf" | "ppt" | "pptx" | "xls" | "xlsx" => FileType::Document,
-            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "svg" => FileType::Image,
-            "mp4" | "avi" | "mkv" | "flv" | "wmv" | "mov" => FileType::Video,
-            "mp3" | "wav" | "flac" | "aac" | "ogg" => FileType::Audio,
-            "js" | "html" | "css" | "py" | "go" | "java" | "cpp" | "c" | "h" | "hpp" | "rs"
-            | "ts" | "json" | "xml" | "yaml" | "toml" => FileType::Document,
-            _ => FileType::Other,
+            JunkFiles::RandomExe => "Installers",
+            JunkFiles::DeletedFiles => "Trash",
+            JunkFiles::TempFiles => "Temporary files",
+            JunkFiles::TempInternetFiles => "Browser caches",
+            JunkFiles::Logs => "Old logs",
+            JunkFiles::Thumbnails => "Thumbnails",
+            JunkFiles::SoftwareLeftovers => "App leftovers",
+        }
+    }
+}
+
+impl JunkFiles {
+    pub const ALL: [JunkFiles; 7] = [
+        JunkFiles::RandomExe,
+        JunkFiles::DeletedFiles,
+        JunkFiles::TempFiles,
+        JunkFiles::TempInternetFiles,
+        JunkFiles::Logs,
+        JunkFiles::Thumbnails,
+        JunkFiles::SoftwareLeftovers,
+    ];
+}
+
+impl From<ContentKind> for FileType {
+    fn from(kind: ContentKind) -> Self {
+        match kind {
+            ContentKind::Image => FileType::Image,
+            ContentKind::Video => FileType::Video,
+            ContentKind::Audio => FileType::Audio,
+            ContentKind::Document => FileType::Document,
+            ContentKind::Other => FileType::Other,
         }
     }
 }
 
 impl FileType {
+    pub const ALL: [FileType; 5] = [
+        FileType::Image,
+        FileType::Video,
+        FileType::Audio,
+        FileType::Document,
+        FileType::Other,
+    ];
+
+    /// The category of a file, from its content.
+    pub fn of(path: &Path) -> Self {
+        ContentKind::from(mime_type::detect(path).as_str()).into()
+    }
+
     pub fn get_image_icon(&self) -> &str {
         match self {
             FileType::Image => "assets/icons/image.png",
@@ -101,28 +223,47 @@ impl FileType {
 #[allow(dead_code)]
 impl StorageAnalysis {
     pub fn new() -> Self {
-        let mut sys = System::new_all();
-        sys.refresh_all();
+        Self::default()
+    }
+
+    /// An analysis keeping the `count` largest files and folders of at least `min_size`.
+    pub fn with_largest(count: usize, min_size: u64) -> Self {
+        StorageAnalysis {
+            largest_files: Largest::new(count, min_size),
+            largest_dirs: Largest::new(count, min_size),
+            ..Self::default()
+        }
+    }
 
-        let total_device_memory = sys.total_memory();
-        let memory_used = sys.used_memory();
+    /// Capacity of the disks holding files of `file_type`.
+    pub fn capacity_of(&self, file_type: &FileType) -> u64 {
+        self.disks_of(file_type).map(|disk| disk.total).sum()
+    }
 
-        let analysis = StorageAnalysis {
-            total_device_memory,
-            memory_usage: MemoryUsage {
-                size: memory_used,
-                ..Default::default()
-            },
-            ..Default::default()
-        };
+    /// The disks holding files of `file_type`.
+    pub fn disks_of<'a>(&'a self, file_type: &FileType) -> impl Iterator<Item = &'a DiskUsage> {
+        let devices = self.file_type_devices.get(file_type);
+        self.disks
+            .iter()
+            .filter(move |disk| devices.is_some_and(|devices| devices.contains(&disk.device)))
+    }
 
-        analysis
+    /// Remember the disk a file is on, the first time a file of its device is seen.
+    fn add_disk(&mut self, path: &PathBuf, device: u64) {
+        if self.disks.iter().any(|disk| disk.device == device) {
+            return;
+        }
+        match DiskUsage::of(path) {
+            Ok(disk) => self.disks.push(disk),
+            Err(e) => eprintln!("Error getting the capacity of the disk of {:?}: {}", path, e),
+        }
     }
 
     pub fn analyse(&mut self, start_path: &PathBuf) {
         let mut dir_queue: VecDeque<PathBuf> = VecDeque::new();
         dir_queue.push_back(start_path.to_path_buf());
         let mut unpermitted_dirs: HashSet<PathBuf> = HashSet::new();
+        self.dir_tree.add_root(start_path);
 
         while let Some(dir) = dir_queue.pop_front() {
             let dir_parent = dir.parent().expect("Error getting parent directory");
@@ -133,6 +274,7 @@ impl StorageAnalysis {
 
             if dir.is_dir() {
                 self.memory_usage.total_folders += 1;
+                self.folders.insert(dir.clone());
                 match fs::read_dir(&dir) {
                     Ok(entries) => {
                         for entry in entries.flatten() {
@@ -150,56 +292,164 @@ impl StorageAnalysis {
                 self.process_file(&dir);
             }
         }
+
+        // folder sizes are only complete once everything below them is analysed
+        for (dir, node) in self.dir_tree.dirs() {
+            if dir == start_path
+                || !dir.starts_with(start_path)
+                || !self.largest_dirs.accepts(node.size)
+            {
+                continue;
+            }
+            let accessed = fs::metadata(dir)
+                .and_then(|metadata| metadata.accessed())
+                .map(DateTime::<Utc>::from)
+                .unwrap_or_default();
+            self.largest_dirs.push(LargeEntry {
+                path: dir.clone(),
+                size: node.size,
+                file_type: None,
+                accessed,
+            });
+        }
     }
 
     fn process_file(&mut self, path: &PathBuf) {
         if let Ok(metadata) = fs::metadata(path) {
             let file_size = metadata.len();
-            let file_extension = path
-                .extension()
-                .and_then(std::ffi::OsStr::to_str)
-                .unwrap_or("");
-            let file_type: FileType = file_extension.into();
-
-            let entry = self
-                .file_types_info
-                .entry(file_type.clone())
-                .or_insert((0, 0));
-            entry.0 += file_size;
-            entry.1 += 1;
-
-            // check for recent created or modified file in the last 7 days and inactive files
-            let (created, accessed, modified) = self.get_file_timestamps(path);
-            if modified > (Utc::now() - chrono::Duration::days(7))
-                || created > (Utc::now() - chrono::Duration::days(7))
-                || accessed > (Utc::now() - chrono::Duration::days(7))
-            {
-                self.recent_files.push(path.clone());
-            } else if accessed < (Utc::now() - chrono::Duration::days(365)) {
-                self.inactive_files.push(path.clone());
+            let file_type = FileType::of(path);
+            self.count_file(path, file_type.clone(), &metadata);
+
+            self.dir_tree.add_file(path, file_size);
+            self.add_disk(path, metadata.dev());
+
+            if self.largest_files.accepts(file_size) {
+                let (_, accessed, _) = self.get_file_timestamps(&metadata);
+                self.largest_files.push(LargeEntry {
+                    path: path.clone(),
+                    size: file_size,
+                    file_type: Some(file_type),
+                    accessed,
+                });
             }
+        }
+    }
+
+    /// Add a file to the totals of its type, and to the recent or stale files.
+    fn count_file(&mut self, path: &Path, file_type: FileType, metadata: &fs::Metadata) {
+        let file_size = metadata.len();
+
+        self.memory_usage.size += file_size;
+        self.files.insert(path.to_path_buf(), (file_type.clone(), file_size));
+        self.file_type_devices
+            .entry(file_type.clone())
+            .or_default()
+            .insert(metadata.dev());
+
+        let entry = self
+            .file_types_info
+            .entry(file_type.clone())
+            .or_insert((0, 0));
+        entry.0 += file_size;
+        entry.1 += 1;
 
-            self.detect_junk_files();
+        // check for recent created or modified file in the last 7 days and inactive files
+        let (created, accessed, modified) = self.get_file_timestamps(metadata);
+        if modified > (Utc::now() - chrono::Duration::days(7))
+            || created > (Utc::now() - chrono::Duration::days(7))
+            || accessed > (Utc::now() - chrono::Duration::days(7))
+        {
+            self.recent_files.push(path.to_path_buf());
+        } else {
+            self.stale_files.add(path, metadata, Utc::now());
         }
     }
 
-    fn get_file_timestamps(&self, path: &PathBuf) -> (DateTime<Utc>, DateTime<Utc>, DateTime<Utc>) {
-        let metadata = fs::metadata(path).expect("Error getting file metadata");
+    fn get_file_timestamps(
+        &self,
+        metadata: &fs::Metadata,
+    ) -> (DateTime<Utc>, DateTime<Utc>, DateTime<Utc>) {
         let modified = metadata
             .modified()
             .map(|t| DateTime::<Utc>::from(t))
-            .unwrap();
+            .unwrap_or_default();
         let created = metadata
             .created()
             .map(|t| DateTime::<Utc>::from(t))
-            .unwrap();
+            .unwrap_or_default();
         let accessed = metadata
             .accessed()
             .map(|t| DateTime::<Utc>::from(t))
-            .unwrap();
+            .unwrap_or_default();
         (created, accessed, modified)
     }
 
+    /// Bring the analysis up to date with an action of the file watcher, without
+    /// analysing everything again.
+    pub fn apply(&mut self, action: &Action, path: &Path) {
+        self.merge(AnalysisDelta::of(action, path));
+    }
+
+    /// Bring in what an action changed, as read by `AnalysisDelta::of`.
+    pub fn merge(&mut self, delta: AnalysisDelta) {
+        if let Some(forgotten) = &delta.forgotten {
+            self.forget(forgotten);
+        }
+        for folder in delta.folders {
+            if self.folders.insert(folder) {
+                self.memory_usage.total_folders += 1;
+            }
+        }
+        for (path, file_type, metadata) in delta.files {
+            self.memory_usage.total_files += 1;
+            self.count_file(&path, file_type, &metadata);
+        }
+        if let Some(accessed) = delta.accessed {
+            if self.files.contains_key(&accessed) && !self.recent_files.contains(&accessed) {
+                self.stale_files.remove(std::slice::from_ref(&accessed));
+                self.recent_files.push(accessed);
+            }
+        }
+    }
+
+    /// Take a file, or a folder and everything in it, back out of the analysis.
+    fn forget(&mut self, path: &Path) {
+        let removed: Vec<PathBuf> = if self.folders.contains(path) {
+            let folders = self.folders.len();
+            self.folders.retain(|dir| !dir.starts_with(path));
+            self.memory_usage.total_folders = self
+                .memory_usage
+                .total_folders
+                .saturating_sub((folders - self.folders.len()) as u64);
+            self.files
+                .keys()
+                .filter(|file| file.starts_with(path))
+                .cloned()
+                .collect()
+        } else if self.files.contains_key(path) {
+            vec![path.to_path_buf()]
+        } else {
+            return;
+        };
+
+        for file in &removed {
+            let Some((file_type, size)) = self.files.remove(file) else {
+                continue;
+            };
+            self.memory_usage.size = self.memory_usage.size.saturating_sub(size);
+            self.memory_usage.total_files = self.memory_usage.total_files.saturating_sub(1);
+            if let Some(entry) = self.file_types_info.get_mut(&file_type) {
+                entry.0 = entry.0.saturating_sub(size);
+                entry.1 = entry.1.saturating_sub(1);
+                if entry.1 == 0 {
+                    self.file_types_info.remove(&file_type);
+                }
+            }
+        }
+        self.recent_files.retain(|file| !file.starts_with(path));
+        self.stale_files.remove(&removed);
+    }
+
     pub fn is_irrelevant_file(&self, path: &PathBuf) -> bool {
         let irrelevant_file_types = vec![
             // Windows
@@ -222,7 +472,12 @@ impl StorageAnalysis {
         irrelevant_file_types.contains(&file_extension)
     }
 
-    fn detect_junk_files(&mut self) {}
+    /// Look for junk with every rule of the default detector, logs in `roots` included.
+    pub fn detect_junk_files(&mut self, roots: &[PathBuf]) {
+        if let Some(places) = JunkPlaces::of_user(roots) {
+            self.junk_files = JunkDetector::default().detect(&places);
+        }
+    }
 
     pub fn recent_files(&self) {}
 }
@@ -230,6 +485,7 @@ impl StorageAnalysis {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::utils::test_dir::TestDir;
 
     #[test]
     pub fn test_storage_analysis() {
@@ -260,4 +516,54 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_apply_actions() {
+        let base = TestDir::new("apply_actions");
+        fs::create_dir_all(base.join("notes")).unwrap();
+        fs::write(base.join("notes/a.txt"), "first note").unwrap();
+
+        let mut analysis = StorageAnalysis::new();
+        analysis.analyse(&base);
+        assert_eq!(analysis.memory_usage.total_files, 1);
+        assert_eq!(analysis.memory_usage.total_folders, 2);
+        assert_eq!(analysis.file_types_info[&FileType::Document], (10, 1));
+
+        // a new file, then the same one written again
+        let created = base.join("notes/b.txt");
+        fs::write(&created, "second").unwrap();
+        analysis.apply(&Action::Create { moved: false }, &created);
+        fs::write(&created, "second note").unwrap();
+        analysis.apply(&Action::Modify(Change::Content), &created);
+        assert_eq!(analysis.file_types_info[&FileType::Document], (21, 2));
+        assert_eq!(analysis.memory_usage.size, 21);
+        assert!(analysis.recent_files.contains(&created));
+
+        // a change of permissions does not read the file again
+        fs::write(&created, "second note, longer").unwrap();
+        analysis.apply(&Action::Modify(Change::Permissions), &created);
+        assert_eq!(analysis.memory_usage.size, 21);
+        fs::write(&created, "second note").unwrap();
+
+        // a folder moved within the analysed one, then away
+        fs::rename(base.join("notes"), base.join("moved")).unwrap();
+        analysis.apply(
+            &Action::moved(base.join("notes"), base.join("moved")),
+            &base.join("moved"),
+        );
+        assert_eq!(analysis.memory_usage.total_files, 2);
+        assert!(analysis.files.contains_key(&base.join("moved/b.txt")));
+        assert!(!analysis.recent_files.contains(&created));
+
+        fs::remove_file(base.join("moved/a.txt")).unwrap();
+        analysis.apply(&Action::Remove { moved: false }, &base.join("moved/a.txt"));
+        assert_eq!(analysis.file_types_info[&FileType::Document], (11, 1));
+        analysis.apply(&Action::Remove { moved: true }, &base.join("moved"));
+        assert!(analysis.file_types_info.is_empty());
+        assert_eq!(
+            (analysis.memory_usage.total_files, analysis.memory_usage.total_folders),
+            (0, 1)
+        );
+        assert_eq!(analysis.memory_usage.size, 0);
+    }
+
 }
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Sizes of the analysed directories and of every folder below them.
+#[derive(Debug, Clone, Default)]
+pub struct DirTree {
+    /// The analysed directories, in the order they were added.
+    roots: Vec<PathBuf>,
+    nodes: HashMap<PathBuf, DirNode>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirNode {
+    /// Total size of the files below the directory.
+    pub size: u64,
+    pub file_count: u64,
+    /// Subdirectories holding at least one file.
+    pub children: Vec<PathBuf>,
+}
+
+impl DirTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a tree at `root`. Files outside of every root are ignored.
+    pub fn add_root(&mut self, root: &Path) {
+        if !self.roots.iter().any(|known| known == root) {
+            self.roots.push(root.to_path_buf());
+            self.nodes.entry(root.to_path_buf()).or_default();
+        }
+    }
+
+    /// Add the size of a file to its folder and to every folder above it.
+    pub fn add_file(&mut self, path: &Path, size: u64) {
+        let Some(root) = self
+            .roots
+            .iter()
+            .filter(|root| path.starts_with(root) && path != root.as_path())
+            .max_by_key(|root| root.as_os_str().len())
+            .cloned()
+        else {
+            return;
+        };
+
+        let mut dirs: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&root))
+            .collect();
+        // from the root down, so a parent always exists before its children
+        dirs.reverse();
+        for (i, dir) in dirs.iter().enumerate() {
+            if !self.nodes.contains_key(*dir) {
+                self.nodes.insert(dir.to_path_buf(), DirNode::default());
+                if let Some(parent) = i.checked_sub(1).and_then(|i| self.nodes.get_mut(dirs[i])) {
+                    parent.children.push(dir.to_path_buf());
+                }
+            }
+            if let Some(node) = self.nodes.get_mut(*dir) {
+                node.size += size;
+                node.file_count += 1;
+            }
+        }
+    }
+
+    pub fn get(&self, dir: &Path) -> Option<&DirNode> {
+        self.nodes.get(dir)
+    }
+
+    /// Every folder of the tree, in no particular order.
+    pub fn dirs(&self) -> impl Iterator<Item = (&PathBuf, &DirNode)> {
+        self.nodes.iter()
+    }
+
+    /// The subdirectories of `dir`, or the roots for `None`, largest first.
+    pub fn entries(&self, dir: Option<&Path>) -> Vec<(PathBuf, u64)> {
+        let paths = match dir {
+            Some(dir) => self
+                .get(dir)
+                .map_or(&[][..], |node| node.children.as_slice()),
+            None => self.roots.as_slice(),
+        };
+        let mut entries: Vec<(PathBuf, u64)> = paths
+            .iter()
+            .filter_map(|path| Some((path.clone(), self.get(path)?.size)))
+            .filter(|(_, size)| *size > 0)
+            .collect();
+        entries.sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+        entries
+    }
+
+    /// Size of the files directly in `dir`, outside of its subdirectories.
+    pub fn loose_size(&self, dir: &Path) -> u64 {
+        let Some(node) = self.get(dir) else {
+            return 0;
+        };
+        let children: u64 = node
+            .children
+            .iter()
+            .filter_map(|child| self.get(child))
+            .map(|child| child.size)
+            .sum();
+        node.size.saturating_sub(children)
+    }
+
+    /// Size of `dir`, or of every root for `None`.
+    pub fn size(&self, dir: Option<&Path>) -> u64 {
+        match dir {
+            Some(dir) => self.get(dir).map_or(0, |node| node.size),
+            None => self.entries(None).iter().map(|(_, size)| size).sum(),
+        }
+    }
+
+    /// The folder above `dir` in the tree, `None` for a root.
+    pub fn parent(&self, dir: &Path) -> Option<PathBuf> {
+        if self.roots.iter().any(|root| root == dir) {
+            return None;
+        }
+        dir.parent()
+            .filter(|parent| self.nodes.contains_key(*parent))
+            .map(Path::to_path_buf)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Rect {
+            x,
+            y,
+            width: width.max(0.0),
+            height: height.max(0.0),
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.width * self.height
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The rectangle shrunk by `margin` on every side.
+    pub fn inset(&self, margin: f64) -> Self {
+        Rect::new(
+            self.x + margin,
+            self.y + margin,
+            self.width - 2.0 * margin,
+            self.height - 2.0 * margin,
+        )
+    }
+}
+
+/// Split `rect` into one rectangle per size, with areas proportional to the
+/// sizes and as close to squares as possible.
+///
+/// Sizes are expected largest first, the rectangles come in the same order.
+pub fn squarify(sizes: &[u64], rect: Rect) -> Vec<Rect> {
+    let total: u64 = sizes.iter().sum();
+    if total == 0 || rect.area() <= 0.0 {
+        return vec![Rect::new(rect.x, rect.y, 0.0, 0.0); sizes.len()];
+    }
+    let scale = rect.area() / total as f64;
+    let areas: Vec<f64> = sizes.iter().map(|&size| size as f64 * scale).collect();
+
+    let mut rects = Vec::with_capacity(sizes.len());
+    let mut free = rect;
+    let mut start = 0;
+    while start < areas.len() {
+        // grow the row while it makes its rectangles more square
+        let side = free.width.min(free.height);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst_ratio(&areas[start..=end], side) <= worst_ratio(&areas[start..end], side)
+        {
+            end += 1;
+        }
+        free = layout_row(&areas[start..end], free, &mut rects);
+        start = end;
+    }
+    rects
+}
+
+/// The most elongated aspect ratio of a row of `areas` laid along `side`.
+fn worst_ratio(areas: &[f64], side: f64) -> f64 {
+    let sum: f64 = areas.iter().sum();
+    let max = areas.iter().copied().fold(0.0, f64::max);
+    let min = areas.iter().copied().fold(f64::INFINITY, f64::min);
+    if sum <= 0.0 || min <= 0.0 {
+        return f64::INFINITY;
+    }
+    let (sum, side) = (sum * sum, side * side);
+    (side * max / sum).max(sum / (side * min))
+}
+
+/// Lay a row along the shorter side of `free` and return what is left of it.
+fn layout_row(areas: &[f64], free: Rect, rects: &mut Vec<Rect>) -> Rect {
+    let sum: f64 = areas.iter().sum();
+    if free.width >= free.height {
+        let width = if free.height > 0.0 {
+            sum / free.height
+        } else {
+            0.0
+        };
+        let mut y = free.y;
+        for area in areas {
+            let height = if width > 0.0 { area / width } else { 0.0 };
+            rects.push(Rect::new(free.x, y, width, height));
+            y += height;
+        }
+        Rect::new(free.x + width, free.y, free.width - width, free.height)
+    } else {
+        let height = if free.width > 0.0 {
+            sum / free.width
+        } else {
+            0.0
+        };
+        let mut x = free.x;
+        for area in areas {
+            let width = if height > 0.0 { area / height } else { 0.0 };
+            rects.push(Rect::new(x, free.y, width, height));
+            x += width;
+        }
+        Rect::new(free.x, free.y + height, free.width, free.height - height)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dir_tree() {
+        let mut tree = DirTree::new();
+        tree.add_root(Path::new("/home/user/Documents"));
+        tree.add_root(Path::new("/home/user/Music"));
+        tree.add_file(Path::new("/home/user/Documents/a.txt"), 10);
+        tree.add_file(Path::new("/home/user/Documents/work/b.txt"), 30);
+        tree.add_file(Path::new("/home/user/Documents/work/old/c.txt"), 20);
+        tree.add_file(Path::new("/home/user/Music/song.mp3"), 100);
+        tree.add_file(Path::new("/tmp/elsewhere.txt"), 1000);
+
+        let documents = Path::new("/home/user/Documents");
+        let work = Path::new("/home/user/Documents/work");
+        assert_eq!(tree.size(None), 160);
+        assert_eq!(tree.size(Some(documents)), 60);
+        assert_eq!(tree.get(work).unwrap().file_count, 2);
+        assert_eq!(tree.loose_size(documents), 10);
+        assert_eq!(tree.loose_size(work), 30);
+        assert_eq!(
+            tree.entries(None),
+            vec![
+                (PathBuf::from("/home/user/Music"), 100),
+                (documents.to_path_buf(), 60)
+            ]
+        );
+        assert_eq!(
+            tree.entries(Some(documents)),
+            vec![(work.to_path_buf(), 50)]
+        );
+        assert_eq!(tree.parent(work), Some(documents.to_path_buf()));
+        assert_eq!(tree.parent(documents), None);
+    }
+
+    #[test]
+    fn test_squarify() {
+        let bounds = Rect::new(0.0, 0.0, 600.0, 400.0);
+        let sizes = [6, 6, 4, 3, 2, 2, 1];
+        let rects = squarify(&sizes, bounds);
+
+        assert_eq!(rects.len(), sizes.len());
+        let total: u64 = sizes.iter().sum();
+        for (rect, size) in rects.iter().zip(sizes) {
+            let expected = bounds.area() * size as f64 / total as f64;
+            assert!((rect.area() - expected).abs() < 1e-6);
+            assert!(rect.x >= 0.0 && rect.x + rect.width <= 600.0 + 1e-6);
+            assert!(rect.y >= 0.0 && rect.y + rect.height <= 400.0 + 1e-6);
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                let overlap_x = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+                let overlap_y = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+                assert!(overlap_x <= 1e-6 || overlap_y <= 1e-6);
+            }
+        }
+        assert_eq!(
+            squarify(&[1, 1], Rect::new(0.0, 0.0, 2.0, 1.0)),
+            vec![Rect::new(0.0, 0.0, 1.0, 1.0), Rect::new(1.0, 0.0, 1.0, 1.0)]
+        );
+    }
+}
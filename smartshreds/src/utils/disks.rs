@@ -0,0 +1,82 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Capacity of a mounted filesystem, in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub mount_point: PathBuf,
+    /// Device id shared by every file on the filesystem.
+    pub device: u64,
+    pub total: u64,
+    /// Free blocks, including those reserved for the superuser.
+    pub free: u64,
+    /// Free blocks an unprivileged user can write to.
+    pub available: u64,
+}
+
+impl DiskUsage {
+    /// Capacity of the filesystem holding `path`.
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: an all-zero `statvfs` is valid, and it outlives the call that fills it
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let block_size = stat.f_frsize as u64;
+
+        Ok(DiskUsage {
+            mount_point: mount_point(path)?,
+            device: fs::metadata(path)?.dev(),
+            total: stat.f_blocks as u64 * block_size,
+            free: stat.f_bfree as u64 * block_size,
+            available: stat.f_bavail as u64 * block_size,
+        })
+    }
+
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+}
+
+/// The directory `path` is mounted under: its furthest ancestor on the same device.
+pub fn mount_point(path: &Path) -> io::Result<PathBuf> {
+    let path = fs::canonicalize(path)?;
+    let device = fs::metadata(&path)?.dev();
+    let mut mount_point = path.as_path();
+    for ancestor in path.ancestors().skip(1) {
+        if fs::metadata(ancestor)?.dev() != device {
+            break;
+        }
+        mount_point = ancestor;
+    }
+    Ok(mount_point.to_path_buf())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_disk_usage() {
+        let temp_dir = std::env::temp_dir();
+        let usage = DiskUsage::of(&temp_dir).unwrap();
+
+        assert!(fs::canonicalize(&temp_dir)
+            .unwrap()
+            .starts_with(&usage.mount_point));
+        assert_eq!(
+            usage.device,
+            fs::metadata(&usage.mount_point).unwrap().dev()
+        );
+        assert!(usage.free <= usage.total);
+        assert!(usage.available <= usage.free);
+        assert_eq!(usage.used(), usage.total - usage.free);
+        assert_eq!(mount_point(Path::new("/")).unwrap(), PathBuf::from("/"));
+    }
+}
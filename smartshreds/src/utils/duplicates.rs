@@ -1,9 +1,29 @@
 use chrono::prelude::*;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::Read;
-use std::path::PathBuf;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::hash_cache::HashCache;
+use super::mime_type::{self, ContentKind};
+use super::perceptual::{group_similar_images, ImageSimilarity};
+use super::text_similarity::{
+    document_signature, group_similar_documents, is_text_document, DocumentSimilarity,
+};
+
+/// Number of bytes read from the start and from the end of a file for its partial hash.
+const PARTIAL_HASH_SIZE: u64 = 16 * 1024;
+/// Size of the buffer used to stream a file through the full hash.
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+/// Upper bound on the number of workers walking and hashing during a scan.
+const MAX_SCAN_THREADS: usize = 8;
+/// Minimum time between two progress events of a running scan.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub enum DuplicateFilterMode {
@@ -25,124 +45,1354 @@ impl From<&str> for DuplicateFilterMode {
     }
 }
 
-impl DuplicateFilterMode {
-    pub fn from_extension(extension: &str) -> Self {
-        match extension {
-            "txt" | "doc" | "docx" | "pdf" | "ppt" | "pptx" | "xls" | "xlsx" => {
-                DuplicateFilterMode::Documents
-            }
-            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "svg" => DuplicateFilterMode::Images,
-            "mp4" | "avi" | "mkv" | "flv" | "wmv" | "mov" => DuplicateFilterMode::Videos,
-            "mp3" | "wav" | "flac" | "aac" | "ogg" => DuplicateFilterMode::Audios,
-            "js" | "html" | "css" | "py" | "go" | "java" | "cpp" | "c" | "h" | "hpp" | "rs"
-            | "ts" | "json" | "xml" | "yaml" | "toml" => DuplicateFilterMode::Documents,
-            _ => DuplicateFilterMode::Documents,
+impl From<ContentKind> for DuplicateFilterMode {
+    fn from(kind: ContentKind) -> Self {
+        match kind {
+            ContentKind::Image => DuplicateFilterMode::Images,
+            ContentKind::Video => DuplicateFilterMode::Videos,
+            ContentKind::Audio => DuplicateFilterMode::Audios,
+            ContentKind::Document | ContentKind::Other => DuplicateFilterMode::Documents,
         }
     }
 }
 
+impl DuplicateFilterMode {
+    /// The filter a file falls under, from its content.
+    pub fn of(path: &Path) -> Self {
+        ContentKind::from(mime_type::detect(path).as_str()).into()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DupFile {
     pub file_path: PathBuf,
     pub file_name: String,
     pub file_size: u64,
     pub date_created: DateTime<Utc>,
+    pub date_modified: DateTime<Utc>,
+    pub device: u64,
+    pub inode: u64,
 }
 
-pub fn traverse_directory_for_duplicates(
-    path: PathBuf,
-) -> (HashMap<String, Vec<DupFile>>, u64, u64, u64) {
-    let mut dir_queue: Vec<PathBuf> = vec![path];
-    let mut duplicates_map: HashMap<String, Vec<DupFile>> = HashMap::new();
-    let mut hasher = Sha256::new();
-    let mut total_file_count = 0;
+impl DupFile {
+    pub fn new(file_path: PathBuf, metadata: &fs::Metadata) -> Self {
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // not every filesystem records a birth time
+        let date_created = metadata
+            .created()
+            .or_else(|_| metadata.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_default();
+        let date_modified = metadata
+            .modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_default();
+
+        DupFile {
+            file_path,
+            file_name,
+            file_size: metadata.len(),
+            date_created,
+            date_modified,
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        }
+    }
+
+    /// A file that need not exist, for tests that only look at its path and size.
+    #[cfg(test)]
+    pub fn fake(file_path: impl Into<PathBuf>, file_size: u64) -> Self {
+        let file_path = file_path.into();
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        DupFile {
+            file_path,
+            file_name,
+            file_size,
+            date_created: Default::default(),
+            date_modified: Default::default(),
+            device: 0,
+            inode: 0,
+        }
+    }
+}
+
+/// How a duplicate scan walks the directories.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Follow symbolic links to files and directories. Directory loops are
+    /// still detected and walked only once.
+    pub follow_symlinks: bool,
+    /// Also look for images that look alike without being byte-identical.
+    pub similar_images: Option<ImageSimilarity>,
+    /// Also look for text documents that only differ by whitespace or a few edits.
+    pub similar_documents: Option<DocumentSimilarity>,
+}
+
+/// What makes the files of a group alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKind {
+    /// Byte-identical files.
+    #[default]
+    Exact,
+    /// Images that look alike, e.g. the same shot saved at another quality or size.
+    SimilarImages,
+    /// Text documents with mostly the same content, e.g. two drafts of a report.
+    SimilarDocuments,
+    /// Directories holding the same files under the same names.
+    Directories,
+}
+
+impl DuplicateKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuplicateKind::Exact => "Duplicates",
+            DuplicateKind::SimilarImages => "Similar images",
+            DuplicateKind::SimilarDocuments => "Similar documents",
+            DuplicateKind::Directories => "Duplicate folders",
+        }
+    }
+}
+
+/// A file of a group of near-duplicates.
+#[derive(Debug, Clone)]
+pub struct SimilarFile {
+    pub file: DupFile,
+    /// How close the file is to the first file of its group, from 0 to 1.
+    pub similarity: f64,
+}
+
+/// A group of files that are alike without being byte-identical.
+#[derive(Debug, Clone)]
+pub struct SimilarGroup {
+    pub kind: DuplicateKind,
+    pub files: Vec<SimilarFile>,
+}
+
+/// Directories whose whole trees are identical, e.g. a project folder and its backup.
+#[derive(Debug, Clone)]
+pub struct DirectoryGroup {
+    /// The directories, with `file_size` set to the size of everything below them.
+    pub dirs: Vec<DupFile>,
+    /// Number of files below each directory.
+    pub file_count: u64,
+    /// Full hashes of the exact groups whose files all lie in these directories,
+    /// which the group stands for.
+    pub covered_groups: Vec<String>,
+}
+
+impl DirectoryGroup {
+    /// Space freed by keeping a single copy of the tree.
+    pub fn reclaimable_size(&self) -> u64 {
+        self.dirs.iter().skip(1).map(|dir| dir.file_size).sum()
+    }
+}
+
+/// The result of a duplicate scan over one or more directories.
+#[derive(Debug, Default)]
+pub struct DuplicateScan {
+    /// Groups of identical files keyed by their full hash. Each file in a group
+    /// is a distinct inode, so every copy but one can be reclaimed.
+    pub duplicates: HashMap<String, Vec<DupFile>>,
+    /// Sets of paths that are hardlinks to the same inode. They are already
+    /// deduplicated: deleting one of them frees nothing.
+    pub hardlinks: Vec<Vec<DupFile>>,
+    /// Groups of near-duplicates, kept apart from the exact groups.
+    pub similar: Vec<SimilarGroup>,
+    /// Groups of identical directory trees. Only the outermost copies are
+    /// reported, not every identical subdirectory below them.
+    pub directories: Vec<DirectoryGroup>,
+    pub total_file_count: u64,
+    pub duplicates_size: u64,
+    pub duplicates_count: u64,
+    /// Space freed by keeping a single copy of every group.
+    pub reclaimable_size: u64,
+    /// Whether the scan was stopped before it went through every file.
+    pub cancelled: bool,
+}
+
+/// A snapshot of how far a running scan has got.
+#[derive(Debug, Clone, Default)]
+pub struct ScanProgress {
+    pub files_discovered: u64,
+    pub bytes_hashed: u64,
+    pub current_path: PathBuf,
+    pub groups_found: u64,
+}
+
+/// Events published by a running duplicate scan.
+#[derive(Debug)]
+pub enum ScanEvent {
+    Progress(ScanProgress),
+    /// A group of identical files that has been confirmed by its full hash.
+    Group(String, Vec<DupFile>),
+    Similar(SimilarGroup),
+    Directories(DirectoryGroup),
+    Finished(DuplicateScan),
+}
+
+/// Find the duplicate files under all of `roots`.
+///
+/// Files are compared in stages so that only real candidates are read in full:
+/// files are first grouped by size, size collisions are then grouped by a hash of
+/// their first and last `PARTIAL_HASH_SIZE` bytes and only the survivors are
+/// streamed through a full SHA-256. Walking and hashing are spread over a pool of
+/// `scan_threads()` workers, and groups are formed over every root at once so a
+/// file in one root is matched against its copies in the others.
+///
+/// Symbolic links are skipped unless `options.follow_symlinks` is set, and paths
+/// that are hardlinks to an inode already seen are reported in `hardlinks` rather
+/// than as duplicates. Near-duplicate images and documents are looked for after
+/// the exact groups are known when `options.similar_images` and
+/// `options.similar_documents` are set. Directories whose trees only hold
+/// duplicates of each other are reported as a whole in `directories`.
+///
+/// Digests of files that did not change since they were stored in `cache` are
+/// reused instead of reading the files again, and new digests are added to it.
+///
+/// Progress and every confirmed group are published through `on_event` while the
+/// scan runs. Setting `cancelled` stops the workers, and the groups confirmed so
+/// far are returned.
+pub fn traverse_directories_for_duplicates<F>(
+    roots: &[PathBuf],
+    options: &ScanOptions,
+    cache: &Mutex<HashCache>,
+    cancelled: &AtomicBool,
+    on_event: F,
+) -> DuplicateScan
+where
+    F: Fn(ScanEvent) + Sync,
+{
+    let threads = scan_threads();
+    let reporter = ScanReporter::new(cache, cancelled, &on_event);
+
+    let roots = normalize_roots(roots);
+    let files = collect_files(roots.clone(), options, threads, &reporter);
+    let total_file_count = files.len() as u64;
+    let (files, hardlinks) = split_hardlinks(files);
+    let images: Vec<DupFile> = match options.similar_images {
+        Some(_) => files
+            .iter()
+            .filter(|file| is_image(file))
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+    let documents: Vec<DupFile> = match options.similar_documents {
+        Some(_) => files
+            .iter()
+            .filter(|file| is_text_document(file))
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let candidates: Vec<DupFile> = group_by_size(&files).into_iter().flatten().collect();
+    let partial_groups = group_by_partial_hash(candidates, threads, &reporter);
+    let duplicates = group_by_full_hash(partial_groups, threads, &reporter);
+
+    let directories = if reporter.is_cancelled() {
+        Vec::new()
+    } else {
+        find_duplicate_trees(&roots, &files, &hardlinks, &duplicates, &reporter)
+    };
+
+    let mut similar = Vec::new();
+    if let Some(image_similarity) = options.similar_images {
+        similar.extend(find_similar_images(
+            images,
+            &duplicates,
+            image_similarity,
+            threads,
+            &reporter,
+        ));
+    }
+    if let Some(document_similarity) = options.similar_documents {
+        similar.extend(find_similar_documents(
+            documents,
+            &duplicates,
+            document_similarity,
+            threads,
+            &reporter,
+        ));
+    }
+    reporter.report_now(Path::new(""));
+
     let mut duplicates_size = 0;
     let mut duplicates_count = 0;
+    let mut reclaimable_size = 0;
+    duplicates.iter().for_each(|(_, v)| {
+        v.iter().for_each(|f| {
+            duplicates_size += f.file_size;
+            duplicates_count += 1;
+        });
+        reclaimable_size += v.iter().skip(1).map(|f| f.file_size).sum::<u64>();
+    });
 
-    while let Some(dir) = dir_queue.pop() {
-        match fs::read_dir(&dir) {
-            Ok(entries) => {
-                for entry in entries {
-                    match entry {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            if path.is_dir() {
-                                dir_queue.push(path);
-                            } else {
-                                total_file_count += 1;
-                                match File::open(&path) {
-                                    Ok(mut file) => {
-                                        let dup_file = DupFile {
-                                            file_path: path.clone(),
-                                            file_name: path
-                                                .file_name()
-                                                .expect("Error getting file name")
-                                                .to_string_lossy()
-                                                .to_string(),
-                                            file_size: file
-                                                .metadata()
-                                                .expect("Error getting metadata")
-                                                .len(),
-                                            date_created: file
-                                                .metadata()
-                                                .expect("Error getting metadata")
-                                                .created()
-                                                .expect("Error getting created")
-                                                .into(),
-                                        };
-                                        let mut contents = Vec::new();
-                                        match file.read_to_end(&mut contents) {
-                                            Ok(_) => {
-                                                hasher.update(&contents);
-                                                let result = hasher.finalize_reset();
-                                                let hash = format!("{:x}", result);
-                                                duplicates_map
-                                                    .entry(hash)
-                                                    .or_insert_with(Vec::new)
-                                                    .push(dup_file);
-                                            }
-                                            Err(e) => {
-                                                eprintln!("Error reading file: {}", e);
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Error opening file: {}", e);
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error reading entry in {:?}: {}", dir, e);
-                            continue;
-                        }
-                    }
+    DuplicateScan {
+        duplicates,
+        hardlinks,
+        similar,
+        directories,
+        reclaimable_size,
+        total_file_count,
+        duplicates_size,
+        duplicates_count,
+        cancelled: reporter.is_cancelled(),
+    }
+}
+
+/// Shared by the scan workers to count progress, publish events and check for cancellation.
+struct ScanReporter<'a> {
+    cache: &'a Mutex<HashCache>,
+    cancelled: &'a AtomicBool,
+    on_event: &'a (dyn Fn(ScanEvent) + Sync),
+    files_discovered: AtomicU64,
+    bytes_hashed: AtomicU64,
+    groups_found: AtomicU64,
+    last_report: Mutex<Instant>,
+}
+
+impl<'a> ScanReporter<'a> {
+    fn new(
+        cache: &'a Mutex<HashCache>,
+        cancelled: &'a AtomicBool,
+        on_event: &'a (dyn Fn(ScanEvent) + Sync),
+    ) -> Self {
+        ScanReporter {
+            cache,
+            cancelled,
+            on_event,
+            files_discovered: AtomicU64::new(0),
+            bytes_hashed: AtomicU64::new(0),
+            groups_found: AtomicU64::new(0),
+            last_report: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashCache> {
+        self.cache.lock().expect("Hash cache poisoned")
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn file_discovered(&self, path: &Path) {
+        self.files_discovered.fetch_add(1, Ordering::Relaxed);
+        self.report(path);
+    }
+
+    fn bytes_hashed(&self, bytes: u64, path: &Path) {
+        self.bytes_hashed.fetch_add(bytes, Ordering::Relaxed);
+        self.report(path);
+    }
+
+    fn group_found(&self, hash: &str, files: &[DupFile]) {
+        self.groups_found.fetch_add(1, Ordering::Relaxed);
+        (self.on_event)(ScanEvent::Group(hash.to_string(), files.to_vec()));
+    }
+
+    fn similar_group_found(&self, group: &SimilarGroup) {
+        self.groups_found.fetch_add(1, Ordering::Relaxed);
+        (self.on_event)(ScanEvent::Similar(group.clone()));
+    }
+
+    fn directory_group_found(&self, group: &DirectoryGroup) {
+        self.groups_found.fetch_add(1, Ordering::Relaxed);
+        (self.on_event)(ScanEvent::Directories(group.clone()));
+    }
+
+    /// Publish progress, at most once every `PROGRESS_INTERVAL`.
+    fn report(&self, path: &Path) {
+        // a worker that finds another one reporting simply skips its turn
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+        if last_report.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        *last_report = Instant::now();
+        drop(last_report);
+        self.report_now(path);
+    }
+
+    fn report_now(&self, path: &Path) {
+        (self.on_event)(ScanEvent::Progress(ScanProgress {
+            files_discovered: self.files_discovered.load(Ordering::Relaxed),
+            bytes_hashed: self.bytes_hashed.load(Ordering::Relaxed),
+            current_path: path.to_path_buf(),
+            groups_found: self.groups_found.load(Ordering::Relaxed),
+        }));
+    }
+}
+
+/// Number of workers used to walk and hash, bounded so a scan never floods the system.
+fn scan_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .min(MAX_SCAN_THREADS)
+}
+
+/// Canonicalize the scan roots and drop those that are missing or nested inside
+/// another root (e.g. Downloads inside Home), so no file is visited twice.
+fn normalize_roots(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut canonical: Vec<PathBuf> = roots
+        .iter()
+        .filter_map(|root| match fs::canonicalize(root) {
+            Ok(root) => Some(root),
+            Err(e) => {
+                eprintln!("Error resolving scan root {:?}: {}", root, e);
+                None
+            }
+        })
+        .collect();
+    // parents sort before their children
+    canonical.sort();
+    canonical.dedup();
+
+    let mut normalized: Vec<PathBuf> = Vec::new();
+    for root in canonical {
+        if !normalized.iter().any(|kept| root.starts_with(kept)) {
+            normalized.push(root);
+        }
+    }
+    normalized
+}
+
+/// Directories waiting to be read by the walker pool.
+#[derive(Default)]
+struct DirQueue {
+    dirs: Vec<PathBuf>,
+    /// (device, inode) of every directory queued so far, so loops are walked once.
+    visited: HashSet<(u64, u64)>,
+    /// Workers currently reading a directory, which may still push more work.
+    busy: usize,
+}
+
+/// Walk every root with `threads` workers and collect every regular file below them.
+fn collect_files(
+    roots: Vec<PathBuf>,
+    options: &ScanOptions,
+    threads: usize,
+    reporter: &ScanReporter,
+) -> Vec<DupFile> {
+    let visited = roots
+        .iter()
+        .filter_map(|root| fs::metadata(root).ok())
+        .map(|metadata| (metadata.dev(), metadata.ino()))
+        .collect();
+    let queue = Mutex::new(DirQueue {
+        dirs: roots,
+        visited,
+        busy: 0,
+    });
+    let ready = Condvar::new();
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| scope.spawn(|| walk_worker(&queue, &ready, options, reporter)))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Directory walker panicked"))
+            .collect()
+    })
+}
+
+fn walk_worker(
+    queue: &Mutex<DirQueue>,
+    ready: &Condvar,
+    options: &ScanOptions,
+    reporter: &ScanReporter,
+) -> Vec<DupFile> {
+    let mut files = Vec::new();
+
+    loop {
+        let dir = {
+            let mut state = queue.lock().expect("Directory queue poisoned");
+            loop {
+                if reporter.is_cancelled() {
+                    break None;
+                }
+                if let Some(dir) = state.dirs.pop() {
+                    state.busy += 1;
+                    break Some(dir);
+                }
+                if state.busy == 0 {
+                    break None;
                 }
+                state = ready.wait(state).expect("Directory queue poisoned");
             }
+        };
+        let Some(dir) = dir else {
+            ready.notify_all();
+            return files;
+        };
+
+        let subdirs = read_dir_files(&dir, options, &mut files, reporter);
+
+        let mut state = queue.lock().expect("Directory queue poisoned");
+        for (subdir, id) in subdirs {
+            if state.visited.insert(id) {
+                state.dirs.push(subdir);
+            }
+        }
+        state.busy -= 1;
+        ready.notify_all();
+    }
+}
+
+/// Read one directory, pushing its files into `files` and returning its
+/// subdirectories along with their (device, inode).
+fn read_dir_files(
+    dir: &Path,
+    options: &ScanOptions,
+    files: &mut Vec<DupFile>,
+    reporter: &ScanReporter,
+) -> Vec<(PathBuf, (u64, u64))> {
+    let mut subdirs = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading directory: {}", e);
+            return subdirs;
+        }
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
             Err(e) => {
-                eprintln!("Error reading directory: {}", e);
+                eprintln!("Error reading entry in {:?}: {}", dir, e);
                 continue;
             }
+        };
+        let mut path = entry.path();
+        // `DirEntry::file_type` does not follow symbolic links
+        let metadata = match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() => {
+                if !options.follow_symlinks {
+                    continue;
+                }
+                let metadata = fs::metadata(&path);
+                // a linked file is reported under its real path so it is never
+                // mistaken for a hardlink of its target
+                if let (Ok(true), Ok(target)) = (
+                    metadata.as_ref().map(|m| m.is_file()),
+                    fs::canonicalize(&path),
+                ) {
+                    path = target;
+                }
+                metadata
+            }
+            Ok(_) => entry.metadata(),
+            Err(e) => Err(e),
+        };
+
+        match metadata {
+            Ok(metadata) if metadata.is_dir() => {
+                subdirs.push((path, (metadata.dev(), metadata.ino())));
+            }
+            Ok(metadata) if metadata.is_file() => {
+                reporter.file_discovered(&path);
+                files.push(DupFile::new(path, &metadata));
+            }
+            // sockets, pipes and devices are never duplicates
+            Ok(_) => {}
+            Err(e) => eprintln!("Error getting metadata of {:?}: {}", path, e),
         }
     }
 
-    let final_duplicates_map: HashMap<String, Vec<DupFile>> = duplicates_map
+    subdirs
+}
+
+/// Keep a single path per (device, inode) and return the sets of paths that are
+/// hardlinks to the same inode separately.
+fn split_hardlinks(files: Vec<DupFile>) -> (Vec<DupFile>, Vec<Vec<DupFile>>) {
+    let mut inode_map: HashMap<(u64, u64), Vec<DupFile>> = HashMap::new();
+    for file in files {
+        inode_map
+            .entry((file.device, file.inode))
+            .or_default()
+            .push(file);
+    }
+
+    let mut unique = Vec::with_capacity(inode_map.len());
+    let mut hardlinks = Vec::new();
+    for (_, mut links) in inode_map {
+        // the same path can be reached twice through followed symbolic links
+        links.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        links.dedup_by(|a, b| a.file_path == b.file_path);
+        unique.push(links[0].clone());
+        if links.len() > 1 {
+            hardlinks.push(links);
+        }
+    }
+
+    (unique, hardlinks)
+}
+
+/// Run `job` over every item on `threads` workers and collect what it returns, in no
+/// particular order. Workers stop picking up items once the scan is cancelled.
+fn parallel_map<T, R, F>(items: &[T], threads: usize, reporter: &ScanReporter, job: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &T) -> Option<R> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    while !reporter.is_cancelled() {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        results.extend(job(index, item));
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Scan worker panicked"))
+            .collect()
+    })
+}
+
+/// Group files by size, dropping every file whose size is unique.
+fn group_by_size(files: &[DupFile]) -> Vec<Vec<DupFile>> {
+    let mut size_map: HashMap<u64, Vec<&DupFile>> = HashMap::new();
+    for file in files {
+        size_map.entry(file.file_size).or_default().push(file);
+    }
+
+    size_map
+        .into_values()
+        .filter(|v| v.len() > 1)
+        .map(|v| v.into_iter().cloned().collect())
+        .collect()
+}
+
+/// Group same-sized candidates by their partial hash, dropping unique files.
+fn group_by_partial_hash(
+    files: Vec<DupFile>,
+    threads: usize,
+    reporter: &ScanReporter,
+) -> Vec<(String, Vec<DupFile>)> {
+    let hashes = parallel_map(&files, threads, reporter, |index, file| {
+        if let Some(hash) = reporter.cache().partial_hash(file) {
+            return Some((index, hash));
+        }
+
+        let hash = log_hash_error(file, partial_hash(&file.file_path, file.file_size))?;
+        reporter.bytes_hashed(file.file_size.min(2 * PARTIAL_HASH_SIZE), &file.file_path);
+        reporter.cache().insert_partial_hash(file, hash.clone());
+        Some((index, hash))
+    });
+
+    let mut files: Vec<Option<DupFile>> = files.into_iter().map(Some).collect();
+    let mut partial_map: HashMap<String, Vec<DupFile>> = HashMap::new();
+    for (index, hash) in hashes {
+        if let Some(file) = files[index].take() {
+            partial_map.entry(hash).or_default().push(file);
+        }
+    }
+
+    partial_map
         .into_iter()
         .filter(|(_, v)| v.len() > 1)
+        .collect()
+}
+
+/// Split every partially matching group by the full hash of its files, dropping
+/// unique files. Groups are published as soon as they are confirmed.
+fn group_by_full_hash(
+    groups: Vec<(String, Vec<DupFile>)>,
+    threads: usize,
+    reporter: &ScanReporter,
+) -> HashMap<String, Vec<DupFile>> {
+    let confirmed = parallel_map(&groups, threads, reporter, |_, (partial, group)| {
+        let mut full_map: HashMap<String, Vec<DupFile>> = HashMap::new();
+        for file in group {
+            // the partial hash of a small file was already taken over all of it
+            let cached = if file.file_size <= 2 * PARTIAL_HASH_SIZE {
+                Some(partial.clone())
+            } else {
+                reporter.cache().full_hash(file)
+            };
+            let hash = match cached {
+                Some(hash) => hash,
+                None => {
+                    let hash = hash_file(&file.file_path, |read| {
+                        reporter.bytes_hashed(read as u64, &file.file_path);
+                        !reporter.is_cancelled()
+                    });
+                    let Some(hash) = log_hash_error(file, hash) else {
+                        continue;
+                    };
+                    reporter.cache().insert_full_hash(file, hash.clone());
+                    hash
+                }
+            };
+            full_map.entry(hash).or_default().push(file.clone());
+        }
+        if reporter.is_cancelled() {
+            return None;
+        }
+
+        full_map.retain(|_, v| v.len() > 1);
+        full_map
+            .iter()
+            .for_each(|(hash, files)| reporter.group_found(hash, files));
+        Some(full_map)
+    });
+
+    confirmed.into_iter().flatten().collect()
+}
+
+fn is_image(file: &DupFile) -> bool {
+    DuplicateFilterMode::of(&file.file_path) == DuplicateFilterMode::Images
+}
+
+/// Drop the files that are a copy of the first file of an exact group, so a
+/// similar group never repeats an exact one.
+fn without_exact_copies(
+    files: Vec<DupFile>,
+    duplicates: &HashMap<String, Vec<DupFile>>,
+) -> Vec<DupFile> {
+    let copies: HashSet<&Path> = duplicates
+        .values()
+        .flat_map(|files| files.iter().skip(1))
+        .map(|file| file.file_path.as_path())
         .collect();
-    final_duplicates_map.iter().for_each(|(_, v)| {
-        v.iter().for_each(|f| {
-            duplicates_size += f.file_size;
-            duplicates_count += 1;
-        });
+    files
+        .into_iter()
+        .filter(|file| !copies.contains(file.file_path.as_path()))
+        .collect()
+}
+
+/// Publish the clusters of near-duplicates found by a similarity engine.
+fn similar_groups(
+    clusters: Vec<Vec<SimilarFile>>,
+    kind: DuplicateKind,
+    reporter: &ScanReporter,
+) -> Vec<SimilarGroup> {
+    clusters
+        .into_iter()
+        .map(|files| {
+            let group = SimilarGroup { kind, files };
+            reporter.similar_group_found(&group);
+            group
+        })
+        .collect()
+}
+
+/// Group the images that look alike.
+fn find_similar_images(
+    images: Vec<DupFile>,
+    duplicates: &HashMap<String, Vec<DupFile>>,
+    image_similarity: ImageSimilarity,
+    threads: usize,
+    reporter: &ScanReporter,
+) -> Vec<SimilarGroup> {
+    let images = without_exact_copies(images, duplicates);
+
+    let algorithm = image_similarity.algorithm;
+    let hashes = parallel_map(&images, threads, reporter, |_, image| {
+        if let Some(hash) = reporter.cache().image_hash(image, algorithm) {
+            return Some((image.clone(), hash));
+        }
+
+        let hash = algorithm.hash_image(&image.file_path)?;
+        reporter.bytes_hashed(image.file_size, &image.file_path);
+        reporter.cache().insert_image_hash(image, algorithm, hash);
+        Some((image.clone(), hash))
     });
+    if reporter.is_cancelled() {
+        return Vec::new();
+    }
 
-    (
-        final_duplicates_map,
-        total_file_count,
-        duplicates_size,
-        duplicates_count,
+    similar_groups(
+        group_similar_images(hashes, image_similarity.max_distance),
+        DuplicateKind::SimilarImages,
+        reporter,
     )
 }
+
+/// Group the text documents whose contents are mostly the same.
+fn find_similar_documents(
+    documents: Vec<DupFile>,
+    duplicates: &HashMap<String, Vec<DupFile>>,
+    document_similarity: DocumentSimilarity,
+    threads: usize,
+    reporter: &ScanReporter,
+) -> Vec<SimilarGroup> {
+    let documents = without_exact_copies(documents, duplicates);
+
+    let signatures = parallel_map(&documents, threads, reporter, |_, document| {
+        if let Some(signature) = reporter.cache().document_signature(document) {
+            return Some((document.clone(), signature));
+        }
+
+        let signature = match document_signature(&document.file_path) {
+            Ok(signature) => signature?,
+            Err(e) => {
+                eprintln!("Error reading file {:?}: {}", document.file_path, e);
+                return None;
+            }
+        };
+        reporter.bytes_hashed(document.file_size, &document.file_path);
+        reporter
+            .cache()
+            .insert_document_signature(document, signature.clone());
+        Some((document.clone(), signature))
+    });
+    if reporter.is_cancelled() {
+        return Vec::new();
+    }
+
+    similar_groups(
+        group_similar_documents(signatures, document_similarity.min_similarity),
+        DuplicateKind::SimilarDocuments,
+        reporter,
+    )
+}
+
+/// Split `len` items into the groups connected by `pairs`, leaving out the items
+/// that are not connected to any other.
+pub fn connected_groups(
+    len: usize,
+    pairs: impl IntoIterator<Item = (usize, usize)>,
+) -> Vec<Vec<usize>> {
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    // union-find over the pairs
+    let mut parents: Vec<usize> = (0..len).collect();
+    for (i, j) in pairs {
+        let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+        parents[a] = b;
+    }
+
+    let mut groups: Vec<Vec<usize>> = vec![Vec::new(); len];
+    for i in 0..len {
+        let group = root(&mut parents, i);
+        groups[group].push(i);
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// A directory below a scan root, as seen from the files collected under it.
+#[derive(Default)]
+struct DirNode {
+    /// Name, size and full hash of every file, `None` for a file without a copy.
+    files: Vec<(String, u64, Option<String>)>,
+    subdirs: Vec<PathBuf>,
+    size: u64,
+    file_count: u64,
+}
+
+/// Find the directories whose trees are identical: the same names holding the
+/// same contents. Every directory gets a Merkle digest over the full hashes of its
+/// files and the digests of its subdirectories, so a directory with a file that
+/// has no copy anywhere has no digest and neither have its parents.
+fn find_duplicate_trees(
+    roots: &[PathBuf],
+    files: &[DupFile],
+    hardlinks: &[Vec<DupFile>],
+    duplicates: &HashMap<String, Vec<DupFile>>,
+    reporter: &ScanReporter,
+) -> Vec<DirectoryGroup> {
+    let mut hashes: HashMap<&Path, String> = HashMap::new();
+    for (hash, group) in duplicates {
+        for file in group {
+            hashes.insert(&file.file_path, hash.clone());
+        }
+    }
+    // every path of an inode has the content of the path that was hashed
+    for links in hardlinks {
+        let hash = links
+            .iter()
+            .find_map(|link| hashes.get(link.file_path.as_path()).cloned())
+            .unwrap_or_else(|| format!("inode:{}:{}", links[0].device, links[0].inode));
+        for link in links {
+            hashes.insert(&link.file_path, hash.clone());
+        }
+    }
+
+    let is_root = |dir: &Path| roots.iter().any(|root| root == dir);
+    let mut nodes: HashMap<PathBuf, DirNode> = HashMap::new();
+    let mut seen: HashSet<&Path> = HashSet::new();
+    for file in files.iter().chain(hardlinks.iter().flatten()) {
+        // followed symbolic links can lead out of the roots
+        if !seen.insert(&file.file_path)
+            || !roots.iter().any(|root| file.file_path.starts_with(root))
+        {
+            continue;
+        }
+        let Some(parent) = file.file_path.parent().filter(|parent| !is_root(parent)) else {
+            continue;
+        };
+        nodes.entry(parent.to_path_buf()).or_default().files.push((
+            file.file_name.clone(),
+            file.file_size,
+            hashes.get(file.file_path.as_path()).cloned(),
+        ));
+
+        // register the directory with its ancestors, up to the scan root
+        let mut dir = parent;
+        while let Some(parent) = dir.parent().filter(|parent| !is_root(parent)) {
+            let node = nodes.entry(parent.to_path_buf()).or_default();
+            if node.subdirs.iter().any(|subdir| subdir == dir) {
+                break;
+            }
+            node.subdirs.push(dir.to_path_buf());
+            dir = parent;
+        }
+    }
+
+    // children are digested before their parents
+    let mut dirs: Vec<PathBuf> = nodes.keys().cloned().collect();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    let mut digests: HashMap<PathBuf, String> = HashMap::new();
+    for dir in &dirs {
+        let node = &nodes[dir];
+        let mut entries = Vec::new();
+        let mut size = 0;
+        let mut file_count = node.files.len() as u64;
+        let mut complete = true;
+        for (name, file_size, hash) in &node.files {
+            size += file_size;
+            match hash {
+                Some(hash) => entries.push(format!("f {} {}", hash, name)),
+                None => complete = false,
+            }
+        }
+        for subdir in &node.subdirs {
+            let subnode = &nodes[subdir];
+            size += subnode.size;
+            file_count += subnode.file_count;
+            match digests.get(subdir) {
+                Some(digest) => {
+                    let name = subdir.file_name().unwrap_or_default().to_string_lossy();
+                    entries.push(format!("d {} {}", digest, name));
+                }
+                None => complete = false,
+            }
+        }
+
+        let node = nodes.get_mut(dir).expect("Directory without a node");
+        node.size = size;
+        node.file_count = file_count;
+        if complete {
+            entries.sort();
+            let mut hasher = Sha256::new();
+            entries.iter().for_each(|entry| {
+                hasher.update(entry.as_bytes());
+                hasher.update(b"\n");
+            });
+            digests.insert(dir.clone(), format!("{:x}", hasher.finalize()));
+        }
+    }
+
+    let mut trees: HashMap<&str, Vec<&PathBuf>> = HashMap::new();
+    for (dir, digest) in &digests {
+        trees.entry(digest).or_default().push(dir);
+    }
+    trees.retain(|_, dirs| dirs.len() > 1);
+    let duplicated: HashSet<&Path> = trees.values().flatten().map(|dir| dir.as_path()).collect();
+
+    let mut groups = Vec::new();
+    for mut tree_dirs in trees.into_values() {
+        // a copy inside a tree that is itself copied is covered by its parent
+        if tree_dirs.iter().all(|dir| {
+            dir.parent()
+                .is_some_and(|parent| duplicated.contains(parent))
+        }) {
+            continue;
+        }
+        tree_dirs.sort();
+
+        let node = &nodes[tree_dirs[0]];
+        let dirs: Vec<DupFile> = tree_dirs
+            .iter()
+            .filter_map(|dir| match fs::metadata(dir) {
+                Ok(metadata) => {
+                    let mut dir_file = DupFile::new(dir.to_path_buf(), &metadata);
+                    dir_file.file_size = node.size;
+                    Some(dir_file)
+                }
+                Err(e) => {
+                    eprintln!("Error reading directory {:?}: {}", dir, e);
+                    None
+                }
+            })
+            .collect();
+        if dirs.len() < 2 {
+            continue;
+        }
+        let covered_groups = duplicates
+            .iter()
+            .filter(|(_, files)| {
+                files
+                    .iter()
+                    .all(|file| tree_dirs.iter().any(|dir| file.file_path.starts_with(dir)))
+            })
+            .map(|(hash, _)| hash.clone())
+            .collect();
+
+        let group = DirectoryGroup {
+            dirs,
+            file_count: node.file_count,
+            covered_groups,
+        };
+        reporter.directory_group_found(&group);
+        groups.push(group);
+    }
+    groups
+}
+
+/// Log a failed hash, leaving out the interruptions caused by cancelling the scan.
+fn log_hash_error(file: &DupFile, hash: io::Result<String>) -> Option<String> {
+    match hash {
+        Ok(hash) => Some(hash),
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => None,
+        Err(e) => {
+            eprintln!("Error reading file {:?}: {}", file.file_path, e);
+            None
+        }
+    }
+}
+
+/// Hash the first and the last `PARTIAL_HASH_SIZE` bytes of a file.
+///
+/// Files that are small enough to be covered entirely are hashed in full, so for
+/// them the partial hash is the same as `full_hash`.
+pub fn partial_hash(path: &Path, file_size: u64) -> io::Result<String> {
+    if file_size <= 2 * PARTIAL_HASH_SIZE {
+        return full_hash(path);
+    }
+
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; PARTIAL_HASH_SIZE as usize];
+
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+    file.seek(SeekFrom::End(-(PARTIAL_HASH_SIZE as i64)))?;
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+    // the size is part of the digest so it never collides with a full hash
+    hasher.update(file_size.to_le_bytes());
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Stream a file through SHA-256 without loading it into memory.
+pub fn full_hash(path: &Path) -> io::Result<String> {
+    hash_file(path, |_| true)
+}
+
+/// Stream a file through SHA-256, calling `on_chunk` with the size of every chunk
+/// read. Hashing stops with `ErrorKind::Interrupted` when `on_chunk` returns false.
+fn hash_file<F>(path: &Path, on_chunk: F) -> io::Result<String>
+where
+    F: Fn(usize) -> bool,
+{
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        if !on_chunk(read) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "hashing was cancelled",
+            ));
+        }
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    fn scan_dir(root: &Path) -> DuplicateScan {
+        scan(&[root.to_path_buf()])
+    }
+
+    fn scan(roots: &[PathBuf]) -> DuplicateScan {
+        let cache = Mutex::new(HashCache::default());
+        traverse_directories_for_duplicates(
+            roots,
+            &ScanOptions::default(),
+            &cache,
+            &AtomicBool::new(false),
+            |_| {},
+        )
+    }
+
+    #[test]
+    fn test_traverse_directories_for_duplicates() {
+        let scan = scan(&[PathBuf::from("test_dir")]);
+
+        assert_eq!(scan.total_file_count, 3);
+        assert_eq!(scan.duplicates.len(), 1);
+        assert_eq!(scan.duplicates_count, 2);
+        assert_eq!(scan.duplicates_size, 2 * "This dog".len() as u64);
+    }
+
+    #[test]
+    fn test_partial_hash_of_small_file_is_full_hash() {
+        let path = PathBuf::from("test_dir/a.txt");
+        let size = fs::metadata(&path).unwrap().len();
+        assert_eq!(
+            partial_hash(&path, size).unwrap(),
+            full_hash(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_overlapping_roots_are_scanned_once() {
+        let scan = scan(&[
+            PathBuf::from("test_dir/sub_dir"),
+            PathBuf::from("test_dir"),
+            PathBuf::from("test_dir/"),
+        ]);
+
+        assert_eq!(scan.total_file_count, 3);
+        assert_eq!(scan.duplicates_count, 2);
+    }
+
+    #[test]
+    fn test_groups_span_roots() {
+        let base = TestDir::new("groups_span_roots");
+        fs::create_dir_all(base.join("first")).unwrap();
+        fs::create_dir_all(base.join("second")).unwrap();
+        fs::write(base.join("first/a.txt"), "same content").unwrap();
+        fs::write(base.join("second/b.txt"), "same content").unwrap();
+
+        let scan = scan(&[base.join("first"), base.join("second")]);
+
+        assert_eq!(scan.duplicates.len(), 1);
+        assert_eq!(scan.duplicates_count, 2);
+    }
+
+    #[test]
+    fn test_scan_streams_groups() {
+        let groups = Mutex::new(Vec::new());
+        let scan = traverse_directories_for_duplicates(
+            &[PathBuf::from("test_dir")],
+            &ScanOptions::default(),
+            &Mutex::new(HashCache::default()),
+            &AtomicBool::new(false),
+            |event| {
+                if let ScanEvent::Group(hash, _) = event {
+                    groups.lock().unwrap().push(hash);
+                }
+            },
+        );
+
+        let groups = groups.into_inner().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(scan.duplicates.contains_key(&groups[0]));
+    }
+
+    #[test]
+    fn test_cancelled_scan_stops() {
+        let scan = traverse_directories_for_duplicates(
+            &[PathBuf::from("test_dir")],
+            &ScanOptions::default(),
+            &Mutex::new(HashCache::default()),
+            &AtomicBool::new(true),
+            |_| {},
+        );
+
+        assert!(scan.cancelled);
+        assert_eq!(scan.total_file_count, 0);
+        assert!(scan.duplicates.is_empty());
+    }
+
+    #[test]
+    fn test_rescan_reuses_cached_hashes() {
+        let cache = Mutex::new(HashCache::default());
+        let first = traverse_directories_for_duplicates(
+            &[PathBuf::from("test_dir")],
+            &ScanOptions::default(),
+            &cache,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert_eq!(cache.lock().unwrap().len(), 2);
+
+        let bytes_hashed = AtomicU64::new(0);
+        let second = traverse_directories_for_duplicates(
+            &[PathBuf::from("test_dir")],
+            &ScanOptions::default(),
+            &cache,
+            &AtomicBool::new(false),
+            |event| {
+                if let ScanEvent::Progress(progress) = event {
+                    bytes_hashed.store(progress.bytes_hashed, Ordering::Relaxed);
+                }
+            },
+        );
+
+        assert_eq!(bytes_hashed.load(Ordering::Relaxed), 0);
+        assert_eq!(
+            first.duplicates.keys().collect::<Vec<_>>(),
+            second.duplicates.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_small_files_are_read_once() {
+        let base = TestDir::new("small_files_are_read_once");
+        fs::write(base.join("original.txt"), "same content").unwrap();
+        fs::write(base.join("copy.txt"), "same content").unwrap();
+
+        let bytes_hashed = AtomicU64::new(0);
+        let scan = traverse_directories_for_duplicates(
+            std::slice::from_ref(&base),
+            &ScanOptions::default(),
+            &Mutex::new(HashCache::default()),
+            &AtomicBool::new(false),
+            |event| {
+                if let ScanEvent::Progress(progress) = event {
+                    bytes_hashed.store(progress.bytes_hashed, Ordering::Relaxed);
+                }
+            },
+        );
+
+        assert_eq!(scan.duplicates.len(), 1);
+        assert_eq!(
+            scan.duplicates.keys().next(),
+            Some(&full_hash(&base.join("original.txt")).unwrap())
+        );
+        assert_eq!(bytes_hashed.load(Ordering::Relaxed), 2 * 12);
+    }
+
+    #[test]
+    fn test_hardlinks_and_symlinks() {
+        let base = TestDir::new("hardlinks_and_symlinks");
+        fs::create_dir_all(base.join("dir")).unwrap();
+        fs::write(base.join("dir/original.txt"), "same content").unwrap();
+        fs::hard_link(base.join("dir/original.txt"), base.join("dir/hardlink.txt")).unwrap();
+        fs::write(base.join("dir/copy.txt"), "same content").unwrap();
+        std::os::unix::fs::symlink(base.join("dir/copy.txt"), base.join("dir/symlink.txt"))
+            .unwrap();
+        std::os::unix::fs::symlink(base.join("dir"), base.join("dir/loop")).unwrap();
+
+        let scan = traverse_directories_for_duplicates(
+            std::slice::from_ref(&base),
+            &ScanOptions {
+                follow_symlinks: true,
+                ..Default::default()
+            },
+            &Mutex::new(HashCache::default()),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        let default_scan = scan_dir(&base);
+
+        // a hardlink or a symbolic link is never a duplicate of its target
+        for scan in [scan, default_scan] {
+            assert_eq!(scan.duplicates.len(), 1);
+            assert_eq!(scan.duplicates_count, 2);
+            assert_eq!(scan.reclaimable_size, "same content".len() as u64);
+            assert_eq!(scan.hardlinks.len(), 1);
+            assert_eq!(scan.hardlinks[0].len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_similar_documents() {
+        let base = TestDir::new("similar_documents");
+        let report: String = (0..100)
+            .map(|i| format!("paragraph {} of the report\n", i))
+            .collect();
+        fs::write(base.join("report_final.txt"), &report).unwrap();
+        fs::write(base.join("report_final2.txt"), report.replace('\n', "\r\n")).unwrap();
+        fs::write(base.join("report_copy.txt"), &report).unwrap();
+
+        let scan = traverse_directories_for_duplicates(
+            std::slice::from_ref(&base),
+            &ScanOptions {
+                similar_documents: Some(DocumentSimilarity::default()),
+                ..Default::default()
+            },
+            &Mutex::new(HashCache::default()),
+            &AtomicBool::new(false),
+            |_| {},
+        );
+
+        // the exact copy only shows up in the exact group
+        assert_eq!(scan.duplicates.len(), 1);
+        assert_eq!(scan.similar.len(), 1);
+        assert_eq!(scan.similar[0].kind, DuplicateKind::SimilarDocuments);
+        assert_eq!(scan.similar[0].files.len(), 2);
+        assert!(scan.similar[0]
+            .files
+            .iter()
+            .all(|file| file.similarity == 1.0));
+    }
+
+    #[test]
+    fn test_duplicate_trees() {
+        let base = TestDir::new("duplicate_trees");
+        for dir in ["photos", "photos_backup", "other"] {
+            fs::create_dir_all(base.join(dir).join("album")).unwrap();
+        }
+        for dir in ["photos", "photos_backup"] {
+            fs::write(base.join(dir).join("cover.jpg"), "cover").unwrap();
+            fs::write(base.join(dir).join("album/beach.jpg"), "beach").unwrap();
+        }
+        // same contents under another name, and a file that has no copy
+        fs::write(base.join("other/album/beach_copy.jpg"), "beach").unwrap();
+        fs::write(base.join("other/notes.txt"), "unique").unwrap();
+        fs::write(base.join("photos/notes.txt"), "notes").unwrap();
+        fs::write(base.join("photos_backup/notes.txt"), "notes").unwrap();
+
+        let scan = scan_dir(&base);
+
+        // the identical albums inside the copied trees are not reported on their own
+        assert_eq!(scan.directories.len(), 1);
+        let group = &scan.directories[0];
+        let names: Vec<&str> = group
+            .dirs
+            .iter()
+            .map(|dir| dir.file_name.as_str())
+            .collect();
+        assert_eq!(names, ["photos", "photos_backup"]);
+        assert_eq!(group.file_count, 3);
+        assert_eq!(group.reclaimable_size(), 15);
+        // the beach group also has a copy outside the trees
+        assert_eq!(group.covered_groups.len(), 2);
+    }
+}
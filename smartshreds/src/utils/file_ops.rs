@@ -0,0 +1,486 @@
+use chrono::Local;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::reflink;
+use super::shred::{shred, ShredMode};
+
+/// What can be done with the duplicates marked on the Duplicates page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileOperation {
+    /// Move to the Trash of the user.
+    Trash,
+    /// Move into a directory.
+    Move(PathBuf),
+    /// Copy into a directory.
+    Copy(PathBuf),
+    /// Overwrite and remove, for good.
+    Shred(ShredMode),
+}
+
+impl FileOperation {
+    /// Apply the operation to a file or directory and return where it ended up,
+    /// its former path once it is shredded.
+    pub fn apply(&self, path: &Path) -> io::Result<PathBuf> {
+        match self {
+            FileOperation::Trash => match Trash::home() {
+                Some(trash) => trash.trash(path),
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no data directory to hold the Trash",
+                )),
+            },
+            FileOperation::Move(dir) => move_into(path, dir),
+            FileOperation::Copy(dir) => copy_into(path, dir),
+            FileOperation::Shred(mode) => shred(path, *mode).map(|_| path.to_path_buf()),
+        }
+    }
+
+    /// Whether the file is gone from its place once the operation is done.
+    pub fn removes_source(&self) -> bool {
+        !matches!(self, FileOperation::Copy(_))
+    }
+
+    /// Describe the outcome of the operation for `count` files.
+    pub fn describe(&self, count: usize) -> String {
+        match self {
+            FileOperation::Trash => format!("{} files moved to the Trash", count),
+            FileOperation::Move(dir) => format!("{} files moved to {}", count, dir.display()),
+            FileOperation::Copy(dir) => format!("{} files copied to {}", count, dir.display()),
+            FileOperation::Shred(mode) => {
+                format!("{} files shredded ({})", count, mode.description())
+            }
+        }
+    }
+}
+
+/// A Trash directory as laid out by the freedesktop.org Trash specification:
+/// trashed files go to `files/` and a `.trashinfo` file with the same name in
+/// `info/` records where they came from and when.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    pub fn new(dir: PathBuf) -> Self {
+        Trash { dir }
+    }
+
+    /// The home Trash, `$XDG_DATA_HOME/Trash`.
+    pub fn home() -> Option<Self> {
+        dirs::data_dir().map(|data_dir| Trash::new(data_dir.join("Trash")))
+    }
+
+    pub fn files_dir(&self) -> PathBuf {
+        self.dir.join("files")
+    }
+
+    pub fn info_dir(&self) -> PathBuf {
+        self.dir.join("info")
+    }
+
+    /// Move a file or directory to the Trash and return its path inside it.
+    pub fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = std::path::absolute(path)?;
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to trash"))?;
+        fs::create_dir_all(self.files_dir())?;
+        fs::create_dir_all(self.info_dir())?;
+
+        // creating the info file first reserves the name against other trashing programs
+        let (trashed_name, mut info) = (1..)
+            .map(|n| unique_name(name, n))
+            .find_map(|candidate| {
+                if self.files_dir().join(&candidate).exists() {
+                    return None;
+                }
+                let info_path = self.info_path(&candidate);
+                match OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(info_path)
+                {
+                    Ok(info) => Some(Ok((candidate, info))),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+                    Err(e) => Some(Err(e)),
+                }
+            })
+            .expect("Ran out of names in the Trash")?;
+        let info_path = self.info_path(&trashed_name);
+        let trashed_path = self.files_dir().join(&trashed_name);
+
+        let result = write_trash_info(&mut info, &path)
+            .and_then(|_| info.sync_all())
+            .and_then(|_| move_path(&path, &trashed_path));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        Ok(trashed_path)
+    }
+
+    /// The Trash a file was trashed to, from its path inside `files/`.
+    pub fn of(trashed_path: &Path) -> Option<Self> {
+        let files_dir = trashed_path.parent()?;
+        (files_dir.file_name()? == "files")
+            .then(|| files_dir.parent().map(|dir| Trash::new(dir.to_path_buf())))
+            .flatten()
+    }
+
+    /// Put a trashed file back where it came from, without overwriting anything.
+    pub fn restore(&self, trashed_path: &Path, original_path: &Path) -> io::Result<()> {
+        let trashed_name = trashed_path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to restore"))?
+            .to_string_lossy()
+            .to_string();
+        move_back(trashed_path, original_path)?;
+        fs::remove_file(self.info_path(&trashed_name))
+    }
+
+    /// Remove a trashed file for good, with the record of where it came from.
+    pub fn erase(&self, trashed_path: &Path) -> io::Result<()> {
+        remove_path(trashed_path)?;
+        let trashed_name = trashed_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        match fs::remove_file(self.info_path(&trashed_name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn info_path(&self, trashed_name: &str) -> PathBuf {
+        self.info_dir().join(format!("{}.trashinfo", trashed_name))
+    }
+}
+
+fn write_trash_info(info: &mut File, original_path: &Path) -> io::Result<()> {
+    write!(
+        info,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original_path),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )
+}
+
+/// Percent-encode a path as the `Path` key of a `.trashinfo` file expects it.
+fn percent_encode(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|&byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// `name` for the first try, then `name.2`, `name.3`... as the Trash does.
+fn unique_name(name: &std::ffi::OsStr, n: usize) -> String {
+    let name = name.to_string_lossy();
+    if n == 1 {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, n)
+    }
+}
+
+/// A path in `dir` named like `path` that is not taken yet: `name (2).ext`,
+/// `name (3).ext`... when `name.ext` already exists.
+fn free_path_in(dir: &Path, path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let target = dir.join(name);
+    if !target.exists() {
+        return Ok(target);
+    }
+
+    let stem = path.file_stem().unwrap_or(name).to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    Ok((2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|target| !target.exists())
+        .expect("Ran out of names in the directory"))
+}
+
+/// Move a file or directory into `dir`, keeping its name unless it is taken.
+pub fn move_into(path: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let target = free_path_in(dir, path)?;
+    move_path(path, &target)?;
+    Ok(target)
+}
+
+/// Copy a file or directory into `dir`, keeping its name unless it is taken.
+pub fn copy_into(path: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let target = free_path_in(dir, path)?;
+    copy_path(path, &target)?;
+    Ok(target)
+}
+
+/// Rename `from` to `to`, copying then removing it when they are on different filesystems.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_path(from, to)?;
+            remove_path(from)
+        }
+        result => result,
+    }
+}
+
+/// Move `from` back to `to`, failing rather than overwriting a file that took its place.
+pub fn move_back(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_path(from, to)
+}
+
+/// Remove a file, or a directory with everything below it.
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// How a redundant copy was replaced by its keeper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// The filesystem supports reflinks: both stay separate files but share their data blocks.
+    Reflink,
+    /// Both are on the same filesystem and now share their inode.
+    Hardlink,
+    /// The copy was on another filesystem and now points to the keeper.
+    Symlink,
+}
+
+/// Whether two files have the same content, compared byte for byte.
+pub fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut a_buffer = [0; 64 * 1024];
+    let mut b_buffer = [0; 64 * 1024];
+    loop {
+        let read = a.read(&mut a_buffer)?;
+        if read == 0 {
+            // the sizes matched, unless `b` grew meanwhile
+            return Ok(b.read(&mut b_buffer[..1])? == 0);
+        }
+        b.read_exact(&mut b_buffer[..read])?;
+        if a_buffer[..read] != b_buffer[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Replace `copy` with a link to `keeper` once their content is checked to be
+/// identical: a reflink where the filesystem supports them, else a hardlink on the
+/// same filesystem and a symlink across filesystems. The link is made next to
+/// `copy` and renamed over it, so `copy` never goes missing.
+pub fn replace_with_link(copy: &Path, keeper: &Path) -> io::Result<LinkKind> {
+    use std::os::unix::fs::MetadataExt;
+
+    let copy_metadata = fs::symlink_metadata(copy)?;
+    let keeper_metadata = fs::metadata(keeper)?;
+    if !copy_metadata.is_file() || !keeper_metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only regular files can be replaced by links",
+        ));
+    }
+    if (copy_metadata.dev(), copy_metadata.ino()) == (keeper_metadata.dev(), keeper_metadata.ino())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} is already a link to {}",
+                copy.display(),
+                keeper.display()
+            ),
+        ));
+    }
+    if !same_content(copy, keeper)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} differs from {}", copy.display(), keeper.display()),
+        ));
+    }
+
+    let same_device = copy_metadata.dev() == keeper_metadata.dev();
+    if same_device && copy.parent().is_some_and(reflink::is_supported) {
+        match reflink::dedupe(copy, keeper) {
+            Ok(()) => return Ok(LinkKind::Reflink),
+            Err(e) => eprintln!(
+                "Error deduplicating {}, linking it instead: {}",
+                copy.display(),
+                e
+            ),
+        }
+    }
+
+    let name = copy
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let link_path = copy.with_file_name(format!(".{}.smartshreds-link", name.to_string_lossy()));
+    let kind = if same_device {
+        fs::hard_link(keeper, &link_path)?;
+        LinkKind::Hardlink
+    } else {
+        std::os::unix::fs::symlink(std::path::absolute(keeper)?, &link_path)?;
+        LinkKind::Symlink
+    };
+    if let Err(e) = fs::rename(&link_path, copy) {
+        let _ = fs::remove_file(&link_path);
+        return Err(e);
+    }
+    Ok(kind)
+}
+
+/// Copy a file, or a directory with everything below it.
+pub fn copy_path(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)
+    } else if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::set_permissions(to, metadata.permissions())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    #[test]
+    fn test_trash() {
+        let base = TestDir::new("trash");
+        fs::create_dir_all(base.join("dir")).unwrap();
+        let trash = Trash::new(base.join("Trash"));
+
+        let mut trashed = Vec::new();
+        for _ in 0..2 {
+            let path = base.join("dir/my file.txt");
+            fs::write(&path, "content").unwrap();
+            trashed.push(trash.trash(&path).unwrap());
+            assert!(!path.exists());
+        }
+
+        // a second file of the same name does not overwrite the first
+        assert_eq!(trashed[0], trash.files_dir().join("my file.txt"));
+        assert_eq!(trashed[1], trash.files_dir().join("my file.txt.2"));
+        let info = fs::read_to_string(trash.info_dir().join("my file.txt.2.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!(
+            "Path={}/dir/my%20file.txt\n",
+            percent_encode(&base)
+        )));
+        assert!(info.contains("DeletionDate="));
+
+        let original = base.join("dir/my file.txt");
+        assert_eq!(Trash::of(&trashed[1]).unwrap().dir, trash.dir);
+        trash.restore(&trashed[1], &original).unwrap();
+        assert!(original.exists());
+        assert!(!trash.info_dir().join("my file.txt.2.trashinfo").exists());
+        // restoring never overwrites
+        assert!(trash.restore(&trashed[0], &original).is_err());
+        assert!(trashed[0].exists());
+    }
+
+    #[test]
+    fn test_move_and_copy_keep_existing_files() {
+        let base = TestDir::new("move_and_copy");
+        fs::create_dir_all(base.join("from/album")).unwrap();
+        fs::create_dir_all(base.join("to")).unwrap();
+        fs::write(base.join("from/photo.jpg"), "new").unwrap();
+        fs::write(base.join("from/album/cover.jpg"), "cover").unwrap();
+        fs::write(base.join("to/photo.jpg"), "old").unwrap();
+
+        let moved = FileOperation::Move(base.join("to"))
+            .apply(&base.join("from/photo.jpg"))
+            .unwrap();
+        let copied = FileOperation::Copy(base.join("to"))
+            .apply(&base.join("from/album"))
+            .unwrap();
+
+        assert_eq!(moved, base.join("to/photo (2).jpg"));
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(base.join("to/photo.jpg")).unwrap(),
+            "old"
+        );
+        assert!(!base.join("from/photo.jpg").exists());
+        assert_eq!(copied, base.join("to/album"));
+        assert_eq!(
+            fs::read_to_string(base.join("to/album/cover.jpg")).unwrap(),
+            "cover"
+        );
+        assert!(base.join("from/album/cover.jpg").exists());
+    }
+
+    #[test]
+    fn test_replace_with_link() {
+        use std::os::unix::fs::MetadataExt;
+
+        let base = TestDir::new("replace_with_link");
+        let keeper = base.join("keeper.txt");
+        let copy = base.join("copy.txt");
+        let other = base.join("other.txt");
+        fs::write(&keeper, "content").unwrap();
+        fs::write(&copy, "content").unwrap();
+        fs::write(&other, "CONTENT").unwrap();
+
+        if reflink::is_supported(&base) {
+            assert_eq!(
+                replace_with_link(&copy, &keeper).unwrap(),
+                LinkKind::Reflink
+            );
+        } else {
+            assert_eq!(
+                replace_with_link(&copy, &keeper).unwrap(),
+                LinkKind::Hardlink
+            );
+            assert_eq!(
+                fs::metadata(&copy).unwrap().ino(),
+                fs::metadata(&keeper).unwrap().ino()
+            );
+            // linking twice leaves the link alone
+            assert!(replace_with_link(&copy, &keeper).is_err());
+        }
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "content");
+        // so does linking a file that differs
+        assert!(replace_with_link(&other, &keeper).is_err());
+        assert_eq!(fs::read_to_string(&other).unwrap(), "CONTENT");
+        assert_eq!(fs::read_dir(&base).unwrap().count(), 3);
+    }
+}
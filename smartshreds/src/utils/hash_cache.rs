@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use super::duplicates::DupFile;
+use super::perceptual::PerceptualHash;
+use super::text_similarity::DocumentSignature;
+
+/// Name of the cache file inside `$XDG_CACHE_HOME/smartshreds`.
+const CACHE_FILE_NAME: &str = "hashes.json";
+
+/// What identifies a version of a file: if any of these change the stored
+/// digests are stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    device: u64,
+    inode: u64,
+    size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    modified: i64,
+}
+
+impl FileStamp {
+    fn from_dup_file(file: &DupFile) -> Self {
+        FileStamp {
+            device: file.device,
+            inode: file.inode,
+            size: file.file_size,
+            modified: file.date_modified.timestamp_nanos_opt().unwrap_or_default(),
+        }
+    }
+
+    fn from_metadata(metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        FileStamp {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.len(),
+            modified: metadata
+                .mtime()
+                .saturating_mul(1_000_000_000)
+                .saturating_add(metadata.mtime_nsec()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    stamp: FileStamp,
+    partial_hash: Option<String>,
+    full_hash: Option<String>,
+    #[serde(default)]
+    image_hash: Option<(PerceptualHash, u64)>,
+    #[serde(default)]
+    document_signature: Option<DocumentSignature>,
+}
+
+/// Digests of previously hashed files, persisted under the XDG cache directory so
+/// that rescans only read the files that changed since they were last hashed.
+#[derive(Debug, Default)]
+pub struct HashCache {
+    /// Where the cache is saved, `None` for a cache that only lives in memory.
+    path: Option<PathBuf>,
+    /// Keyed by `cache_key` of the file path.
+    entries: HashMap<String, CacheEntry>,
+    dirty: bool,
+}
+
+impl HashCache {
+    /// Open the cache of the current user, starting empty if it is missing or unreadable.
+    pub fn open() -> Self {
+        match dirs::cache_dir() {
+            Some(cache_dir) => Self::load(cache_dir.join("smartshreds").join(CACHE_FILE_NAME)),
+            None => Self::default(),
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                eprintln!("Discarding corrupt hash cache {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                eprintln!("Error reading hash cache {:?}: {}", path, e);
+                HashMap::new()
+            }
+        };
+
+        HashCache {
+            path: Some(path),
+            entries,
+            dirty: false,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn partial_hash(&self, file: &DupFile) -> Option<String> {
+        self.valid_entry(file)?.partial_hash.clone()
+    }
+
+    pub fn full_hash(&self, file: &DupFile) -> Option<String> {
+        self.valid_entry(file)?.full_hash.clone()
+    }
+
+    /// The perceptual hash of an image, if it was computed with `algorithm`.
+    pub fn image_hash(&self, file: &DupFile, algorithm: PerceptualHash) -> Option<u64> {
+        match self.valid_entry(file)?.image_hash {
+            Some((stored_algorithm, hash)) if stored_algorithm == algorithm => Some(hash),
+            _ => None,
+        }
+    }
+
+    pub fn document_signature(&self, file: &DupFile) -> Option<DocumentSignature> {
+        self.valid_entry(file)?.document_signature.clone()
+    }
+
+    pub fn insert_partial_hash(&mut self, file: &DupFile, hash: String) {
+        self.entry(file).partial_hash = Some(hash);
+    }
+
+    pub fn insert_full_hash(&mut self, file: &DupFile, hash: String) {
+        self.entry(file).full_hash = Some(hash);
+    }
+
+    pub fn insert_image_hash(&mut self, file: &DupFile, algorithm: PerceptualHash, hash: u64) {
+        self.entry(file).image_hash = Some((algorithm, hash));
+    }
+
+    pub fn insert_document_signature(&mut self, file: &DupFile, signature: DocumentSignature) {
+        self.entry(file).document_signature = Some(signature);
+    }
+
+    /// Drop the entries of files that no longer exist or changed since they were
+    /// hashed, the ones deleted or replaced by a cleanup included.
+    pub fn compact(&mut self) {
+        let before = self.entries.len();
+        self.entries
+            .retain(|key, entry| match path_of_key(key).map(fs::metadata) {
+                Some(Ok(metadata)) => FileStamp::from_metadata(&metadata) == entry.stamp,
+                _ => false,
+            });
+        self.dirty |= self.entries.len() != before;
+    }
+
+    /// Write the cache back to disk if anything changed since it was loaded.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // write to a temporary file first so a crash never leaves a truncated cache
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&tmp_path, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn valid_entry(&self, file: &DupFile) -> Option<&CacheEntry> {
+        self.entries
+            .get(&cache_key(&file.file_path))
+            .filter(|entry| entry.stamp == FileStamp::from_dup_file(file))
+    }
+
+    /// The entry of `file`, reset first if the file changed since it was stored.
+    fn entry(&mut self, file: &DupFile) -> &mut CacheEntry {
+        self.dirty = true;
+        let stamp = FileStamp::from_dup_file(file);
+        let entry = self
+            .entries
+            .entry(cache_key(&file.file_path))
+            .or_insert(CacheEntry {
+                stamp,
+                partial_hash: None,
+                full_hash: None,
+                image_hash: None,
+                document_signature: None,
+            });
+        if entry.stamp != stamp {
+            *entry = CacheEntry {
+                stamp,
+                partial_hash: None,
+                full_hash: None,
+                image_hash: None,
+                document_signature: None,
+            };
+        }
+        entry
+    }
+}
+
+/// The bytes of a path in hex: paths need not be UTF-8, and a lossy conversion
+/// would give different files the same key.
+fn cache_key(path: &Path) -> String {
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .fold(String::new(), |mut key, byte| {
+            let _ = write!(key, "{:02x}", byte);
+            key
+        })
+}
+
+/// The path a key was made from, `None` for keys that are not `cache_key`s.
+fn path_of_key(key: &str) -> Option<PathBuf> {
+    if !key.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..key.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(key.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(PathBuf::from(OsStr::from_bytes(&bytes)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    fn dup_file(path: &Path) -> DupFile {
+        DupFile::new(path.to_path_buf(), &fs::metadata(path).unwrap())
+    }
+
+    #[test]
+    fn test_hash_cache_round_trip() {
+        let base = TestDir::new("hash_cache_round_trip");
+        let file_path = base.join("file.txt");
+        fs::write(&file_path, "content").unwrap();
+        let cache_path = base.join("cache").join(CACHE_FILE_NAME);
+
+        let mut cache = HashCache::load(cache_path.clone());
+        cache.insert_full_hash(&dup_file(&file_path), "digest".to_string());
+        cache.save().unwrap();
+
+        let mut cache = HashCache::load(cache_path);
+        assert_eq!(
+            cache.full_hash(&dup_file(&file_path)),
+            Some("digest".to_string())
+        );
+        assert_eq!(cache.partial_hash(&dup_file(&file_path)), None);
+
+        // a changed file no longer matches its entry and is dropped by compaction
+        fs::write(&file_path, "changed content").unwrap();
+        assert_eq!(cache.full_hash(&dup_file(&file_path)), None);
+        cache.compact();
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_hash_cache_non_utf8_paths() {
+        let base = TestDir::new("hash_cache_non_utf8_paths");
+        // both names read as "\u{FFFD}.txt" when converted lossily
+        let first = base.join(OsStr::from_bytes(b"\xff.txt"));
+        let second = base.join(OsStr::from_bytes(b"\xfe.txt"));
+        fs::write(&first, "first").unwrap();
+        fs::write(&second, "second").unwrap();
+        let cache_path = base.join("cache").join(CACHE_FILE_NAME);
+
+        let mut cache = HashCache::load(cache_path.clone());
+        cache.insert_full_hash(&dup_file(&first), "first digest".to_string());
+        cache.insert_full_hash(&dup_file(&second), "second digest".to_string());
+        cache.save().unwrap();
+
+        let mut cache = HashCache::load(cache_path);
+        cache.compact();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.full_hash(&dup_file(&first)),
+            Some("first digest".to_string())
+        );
+        assert_eq!(
+            cache.full_hash(&dup_file(&second)),
+            Some("second digest".to_string())
+        );
+        assert_eq!(path_of_key(&cache_key(&first)), Some(first));
+        assert_eq!(path_of_key("/not/a/key"), None);
+    }
+}
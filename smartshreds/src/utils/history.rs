@@ -0,0 +1,321 @@
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use super::recents::{Action, ActionEvent, ActionKind, Change, Node};
+
+/// Name of the history file inside `$XDG_DATA_HOME/smartshreds`, one JSON record per line.
+const HISTORY_FILE_NAME: &str = "activity.jsonl";
+
+/// Something that happened to a file or folder of a watched folder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    /// When it happened, in seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub path: PathBuf,
+    pub action: ActionKind,
+    /// What was modified, or `Moved` for an entry moved in or away.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<Change>,
+    pub node: Node,
+    /// Where a renamed or moved entry was before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+}
+
+impl ActivityRecord {
+    pub fn of(event: &ActionEvent) -> Self {
+        ActivityRecord {
+            timestamp: event.time.timestamp(),
+            path: event.file_path.clone(),
+            action: event.action.kind(),
+            change: event.action.change(),
+            node: event.node,
+            from: event.action.from().map(Path::to_path_buf),
+        }
+    }
+
+    /// The action recorded, with its details.
+    pub fn to_action(&self) -> Action {
+        Action::of_parts(self.action, self.change, self.from.clone(), &self.path)
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.timestamp, 0)
+            .single()
+            .unwrap_or_default()
+    }
+}
+
+/// How far back the history is looked at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Hour,
+    Day,
+    Week,
+}
+
+impl From<&Period> for &str {
+    fn from(value: &Period) -> Self {
+        match value {
+            Period::Hour => "Last hour",
+            Period::Day => "Last day",
+            Period::Week => "Last week",
+        }
+    }
+}
+
+impl Period {
+    pub const ALL: [Period; 3] = [Period::Hour, Period::Day, Period::Week];
+
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            Period::Hour => chrono::Duration::hours(1),
+            Period::Day => chrono::Duration::days(1),
+            Period::Week => chrono::Duration::weeks(1),
+        }
+    }
+}
+
+/// Every action seen in the watched folders, appended to a file under the XDG
+/// data directory so the Recents panel remembers them in a later session. The
+/// file is pruned of the records older than a week when it is loaded.
+#[derive(Debug, Default)]
+pub struct ActivityHistory {
+    /// Where the history is saved, `None` for a history that only lives in memory.
+    path: Option<PathBuf>,
+    /// Opened for appending on the first record.
+    file: Option<File>,
+    /// Oldest first.
+    records: Vec<ActivityRecord>,
+}
+
+impl ActivityHistory {
+    /// Open the history of the current user, starting empty if it is missing.
+    pub fn open() -> Self {
+        match dirs::data_dir() {
+            Some(data_dir) => Self::load(data_dir.join("smartshreds").join(HISTORY_FILE_NAME)),
+            None => Self::default(),
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let mut records = Vec::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(e) => {
+                            eprintln!("Error reading activity history {:?}: {}", path, e);
+                            break;
+                        }
+                    };
+                    // a line cut short by a crash is skipped, the others are still good
+                    match serde_json::from_str::<ActivityRecord>(&line) {
+                        Ok(record) => records.push(record),
+                        Err(e) => eprintln!("Skipping corrupt activity record {:?}: {}", line, e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Error opening activity history {:?}: {}", path, e),
+        }
+        records.sort_by_key(|record| record.timestamp);
+
+        // no period looks further back than a week, older records are dropped for good
+        let oldest = (Utc::now() - Period::Week.duration()).timestamp();
+        let expired = records.partition_point(|record| record.timestamp < oldest);
+        if expired > 0 {
+            records.drain(..expired);
+            if let Err(e) = rewrite(&path, &records) {
+                eprintln!("Error pruning activity history {:?}: {}", path, e);
+            }
+        }
+
+        ActivityHistory {
+            path: Some(path),
+            file: None,
+            records,
+        }
+    }
+
+    /// Every record, oldest first.
+    pub fn records(&self) -> &[ActivityRecord] {
+        &self.records
+    }
+
+    /// Add a record and append it to the history file. The record is kept for
+    /// the session even when it could not be saved.
+    pub fn record(&mut self, record: ActivityRecord) -> io::Result<()> {
+        let result = self.append(&record);
+        let position = self
+            .records
+            .partition_point(|other| other.timestamp <= record.timestamp);
+        self.records.insert(position, record);
+        result
+    }
+
+    fn append(&mut self, record: &ActivityRecord) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.file.is_none() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        match &mut self.file {
+            Some(file) => file.write_all(&line),
+            None => Ok(()),
+        }
+    }
+
+    /// The records since `since`, of `folder` and below when given, oldest first.
+    pub fn since(&self, since: DateTime<Utc>, folder: Option<&Path>) -> Vec<&ActivityRecord> {
+        let start = self
+            .records
+            .partition_point(|record| record.timestamp < since.timestamp());
+        self.records[start..]
+            .iter()
+            .filter(|record| folder.is_none_or(|folder| record.path.starts_with(folder)))
+            .collect()
+    }
+
+    /// What changed in the last `period`, of `folder` and below when given.
+    pub fn changes(&self, period: Period, folder: Option<&Path>) -> Vec<&ActivityRecord> {
+        self.since(Utc::now() - period.duration(), folder)
+    }
+}
+
+/// Replace the history file with `records`, through a temporary file so a crash
+/// leaves either the old history or the new one.
+fn rewrite(path: &Path, records: &[ActivityRecord]) -> io::Result<()> {
+    let temporary = path.with_extension("jsonl.tmp");
+    let mut file = File::create(&temporary)?;
+    for record in records {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+    }
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// Records, oldest first, grouped by the local day they happened on, latest day
+/// and latest record first.
+pub fn timeline<'a>(records: &[&'a ActivityRecord]) -> Vec<(NaiveDate, Vec<&'a ActivityRecord>)> {
+    let mut days: Vec<(NaiveDate, Vec<&ActivityRecord>)> = Vec::new();
+    for record in records.iter().rev() {
+        let day = record.time().with_timezone(&Local).date_naive();
+        match days.last_mut() {
+            Some((last_day, day_records)) if *last_day == day => day_records.push(record),
+            _ => days.push((day, vec![record])),
+        }
+    }
+    days
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    fn record(path: &str, timestamp: i64, action: ActionKind) -> ActivityRecord {
+        ActivityRecord {
+            timestamp,
+            path: PathBuf::from(path),
+            action,
+            change: None,
+            node: Node::File,
+            from: None,
+        }
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let base = TestDir::new("history");
+        let path = base.join("activity.jsonl");
+
+        let now = Utc::now().timestamp();
+        let mut history = ActivityHistory::load(path.clone());
+        assert!(history.records().is_empty());
+        history
+            .record(record(
+                "/home/a/Documents/old.txt",
+                now - 3 * 86400,
+                ActionKind::Create,
+            ))
+            .unwrap();
+        history
+            .record(record(
+                "/home/a/Downloads/new.iso",
+                now - 600,
+                ActionKind::Modify,
+            ))
+            .unwrap();
+        history
+            .record(record(
+                "/home/a/Documents/report.pdf",
+                now - 7200,
+                ActionKind::Remove,
+            ))
+            .unwrap();
+        history
+            .record(record(
+                "/home/a/Pictures/expired.png",
+                now - 8 * 86400,
+                ActionKind::Create,
+            ))
+            .unwrap();
+        drop(history);
+
+        // a record cut short by a crash does not lose the others
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"timestamp\":1").unwrap();
+
+        let history = ActivityHistory::load(path.clone());
+        let timestamps: Vec<i64> = history.records().iter().map(|r| r.timestamp).collect();
+        assert_eq!(timestamps, vec![now - 3 * 86400, now - 7200, now - 600]);
+        assert_eq!(history.changes(Period::Hour, None).len(), 1);
+        assert_eq!(history.changes(Period::Day, None).len(), 2);
+        assert_eq!(history.changes(Period::Week, None).len(), 3);
+        let documents = Path::new("/home/a/Documents");
+        assert_eq!(
+            history.changes(Period::Day, Some(documents))[0].action,
+            ActionKind::Remove
+        );
+        assert_eq!(history.changes(Period::Week, Some(documents)).len(), 2);
+
+        // the records too old to be shown and the corrupt one are gone from the file
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, 3);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let noon = Local
+            .with_ymd_and_hms(2024, 5, 10, 12, 0, 0)
+            .unwrap()
+            .timestamp();
+        let records = [
+            record("a", noon - 86400, ActionKind::Create),
+            record("b", noon, ActionKind::Modify),
+            record("c", noon + 60, ActionKind::Rename),
+        ];
+        let records: Vec<&ActivityRecord> = records.iter().collect();
+
+        let days = timeline(&records);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].0, NaiveDate::from_ymd_opt(2024, 5, 10).unwrap());
+        let paths: Vec<&str> = days[0].1.iter().map(|r| r.path.to_str().unwrap()).collect();
+        assert_eq!(paths, vec!["c", "b"]);
+        assert_eq!(days[1].1[0].path, PathBuf::from("a"));
+    }
+}
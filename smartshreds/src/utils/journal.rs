@@ -0,0 +1,315 @@
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::file_ops::{copy_path, move_back, remove_path, FileOperation, Trash};
+
+/// Name of the journal file inside `$XDG_DATA_HOME/smartshreds`.
+const JOURNAL_FILE_NAME: &str = "journal.json";
+
+/// Held while the journal file is read and written back, operations run on
+/// several threads at once.
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// What was done to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationKind {
+    Trash,
+    Move,
+    Copy,
+    /// The file was replaced by a link to, or a clone of, an identical file.
+    Replace,
+}
+
+impl OperationKind {
+    /// What is recorded of a file operation, `None` for shredding that cannot be undone.
+    pub fn of(operation: &FileOperation) -> Option<Self> {
+        match operation {
+            FileOperation::Trash => Some(OperationKind::Trash),
+            FileOperation::Move(_) => Some(OperationKind::Move),
+            FileOperation::Copy(_) => Some(OperationKind::Copy),
+            FileOperation::Shred(_) => None,
+        }
+    }
+}
+
+/// One file operation: `source` is where the file was, `destination` is its
+/// path in the Trash, where it was moved or copied to, or the file it was
+/// replaced by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub kind: OperationKind,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    #[serde(default)]
+    pub restored: bool,
+}
+
+impl JournalEntry {
+    pub fn new(kind: OperationKind, source: PathBuf, destination: PathBuf) -> Self {
+        JournalEntry {
+            kind,
+            source,
+            destination,
+            restored: false,
+        }
+    }
+
+    /// Whether the entry can still be undone.
+    pub fn can_undo(&self) -> bool {
+        !self.restored && fs::symlink_metadata(&self.destination).is_ok()
+    }
+
+    /// Undo the operation and return the path that was put back, or where the
+    /// copy is in `trash`. The copy may have been changed since, so it is moved
+    /// to the Trash rather than removed for good.
+    fn undo(&self, trash: Option<&Trash>) -> io::Result<PathBuf> {
+        match self.kind {
+            OperationKind::Trash => {
+                let trash = Trash::of(&self.destination).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not in a Trash", self.destination.display()),
+                    )
+                })?;
+                trash.restore(&self.destination, &self.source)?;
+                Ok(self.source.clone())
+            }
+            OperationKind::Move => {
+                move_back(&self.destination, &self.source)?;
+                Ok(self.source.clone())
+            }
+            OperationKind::Copy => match trash {
+                Some(trash) => trash.trash(&self.destination),
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no data directory to hold the Trash",
+                )),
+            },
+            OperationKind::Replace => {
+                restore_replaced(&self.destination, &self.source)?;
+                Ok(self.source.clone())
+            }
+        }
+    }
+}
+
+/// Give a replaced file its own content again, swapping it in atomically.
+fn restore_replaced(kept: &Path, replaced: &Path) -> io::Result<()> {
+    let name = replaced
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to restore"))?;
+    let tmp_path =
+        replaced.with_file_name(format!(".{}.smartshreds-restore", name.to_string_lossy()));
+    copy_path(kept, &tmp_path)?;
+    fs::rename(&tmp_path, replaced).inspect_err(|_| {
+        let _ = remove_path(&tmp_path);
+    })
+}
+
+/// The file operations of one cleanup, undone together.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalBatch {
+    pub id: u64,
+    /// When the batch was recorded, in seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub description: String,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl JournalBatch {
+    pub fn date(&self) -> Option<DateTime<Local>> {
+        Local.timestamp_opt(self.timestamp, 0).single()
+    }
+
+    /// Whether any entry of the batch can still be undone.
+    pub fn can_undo(&self) -> bool {
+        self.entries.iter().any(JournalEntry::can_undo)
+    }
+
+    /// Whether every entry of the batch was undone.
+    pub fn is_undone(&self) -> bool {
+        self.entries.iter().all(|entry| entry.restored)
+    }
+}
+
+/// Every trash, move, copy and replace done by the app, persisted under the XDG
+/// data directory so cleanups can be undone in a later session too.
+#[derive(Debug, Default)]
+pub struct Journal {
+    /// Where the journal is saved, `None` for a journal that only lives in memory.
+    path: Option<PathBuf>,
+    batches: Vec<JournalBatch>,
+    /// Where undone copies go.
+    trash: Option<Trash>,
+}
+
+impl Journal {
+    /// Open the journal of the current user, starting empty if it is missing or unreadable.
+    pub fn open() -> Self {
+        match dirs::data_dir() {
+            Some(data_dir) => Self::load(data_dir.join("smartshreds").join(JOURNAL_FILE_NAME)),
+            None => Self::default(),
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let batches = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                eprintln!("Discarding corrupt journal {:?}: {}", path, e);
+                Vec::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                eprintln!("Error reading journal {:?}: {}", path, e);
+                Vec::new()
+            }
+        };
+
+        Journal {
+            path: Some(path),
+            batches,
+            trash: Trash::home(),
+        }
+    }
+
+    /// The recorded batches, oldest first.
+    pub fn batches(&self) -> &[JournalBatch] {
+        &self.batches
+    }
+
+    /// Record a batch of operations and return its id. Empty batches are not recorded.
+    pub fn record(&mut self, description: String, entries: Vec<JournalEntry>) -> Option<u64> {
+        if entries.is_empty() {
+            return None;
+        }
+        let id = self.batches.last().map_or(1, |batch| batch.id + 1);
+        self.batches.push(JournalBatch {
+            id,
+            timestamp: Local::now().timestamp(),
+            description,
+            entries,
+        });
+        Some(id)
+    }
+
+    /// The most recent batch that can still be undone.
+    pub fn last_undoable(&self) -> Option<&JournalBatch> {
+        self.batches.iter().rev().find(|batch| batch.can_undo())
+    }
+
+    /// Undo the entries of a batch that were not undone yet, last one first.
+    pub fn undo(&mut self, id: u64) -> Vec<io::Result<PathBuf>> {
+        let Some(batch) = self.batches.iter_mut().find(|batch| batch.id == id) else {
+            return Vec::new();
+        };
+        batch
+            .entries
+            .iter_mut()
+            .rev()
+            .filter(|entry| !entry.restored)
+            .map(|entry| {
+                let result = entry.undo(self.trash.as_ref());
+                entry.restored = result.is_ok();
+                result
+            })
+            .collect()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // write to a temporary file first so a crash never loses the history
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&self.batches)?)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// Open the journal of the current user, change it and save it back, one thread at a time.
+pub fn update_journal<T>(f: impl FnOnce(&mut Journal) -> T) -> T {
+    let _lock = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut journal = Journal::open();
+    let result = f(&mut journal);
+    if let Err(e) = journal.save() {
+        eprintln!("Error saving journal: {}", e);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    #[test]
+    fn test_journal_undo() {
+        let base = TestDir::new("journal_undo");
+        fs::create_dir_all(base.join("files")).unwrap();
+        fs::create_dir_all(base.join("to")).unwrap();
+        let trash = Trash::new(base.join("Trash"));
+        for name in ["trashed.txt", "moved.txt", "copied.txt"] {
+            fs::write(base.join("files").join(name), name).unwrap();
+        }
+
+        let trashed = trash.trash(&base.join("files/trashed.txt")).unwrap();
+        let moved = FileOperation::Move(base.join("to"))
+            .apply(&base.join("files/moved.txt"))
+            .unwrap();
+        let copied = FileOperation::Copy(base.join("to"))
+            .apply(&base.join("files/copied.txt"))
+            .unwrap();
+
+        let journal_path = base.join("journal").join(JOURNAL_FILE_NAME);
+        let mut journal = Journal::load(journal_path.clone());
+        assert_eq!(journal.record("nothing".to_string(), Vec::new()), None);
+        let id = journal
+            .record(
+                "cleanup".to_string(),
+                vec![
+                    JournalEntry::new(
+                        OperationKind::Trash,
+                        base.join("files/trashed.txt"),
+                        trashed,
+                    ),
+                    JournalEntry::new(OperationKind::Move, base.join("files/moved.txt"), moved),
+                    JournalEntry::new(
+                        OperationKind::Copy,
+                        base.join("files/copied.txt"),
+                        copied.clone(),
+                    ),
+                ],
+            )
+            .unwrap();
+        journal.save().unwrap();
+
+        let mut journal = Journal::load(journal_path);
+        journal.trash = Some(trash.clone());
+        assert_eq!(journal.last_undoable().map(|batch| batch.id), Some(id));
+        assert!(journal.undo(id).iter().all(Result::is_ok));
+        for name in ["trashed.txt", "moved.txt", "copied.txt"] {
+            assert_eq!(
+                fs::read_to_string(base.join("files").join(name)).unwrap(),
+                name
+            );
+        }
+        // the copy may have been edited since, it can still be found in the Trash
+        assert!(!copied.exists());
+        assert_eq!(
+            fs::read_to_string(trash.files_dir().join("copied.txt")).unwrap(),
+            "copied.txt"
+        );
+        assert!(journal.batches()[0].is_undone());
+        assert!(journal.last_undoable().is_none());
+        // undoing twice does nothing
+        assert!(journal.undo(id).is_empty());
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+use super::analysis::JunkFiles;
+use super::file_ops::{remove_path, FileOperation, Trash};
+
+/// Temporary files untouched for this long are no longer used by whoever made them.
+const TEMP_FILE_MIN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Extensions of installers, usually left in Downloads once they have run.
+const INSTALLER_EXTENSIONS: &[&str] = &[
+    "exe",
+    "msi",
+    "appimage",
+    "deb",
+    "rpm",
+    "run",
+    "dmg",
+    "pkg",
+    "flatpakref",
+];
+
+/// Browser cache directories: the browser directory in the cache of the user,
+/// then the cache directories found in each of its profiles.
+const BROWSER_CACHES: &[(&str, &[&str])] = &[
+    ("mozilla/firefox", &["cache2"]),
+    ("google-chrome", &["Cache", "Code Cache"]),
+    ("chromium", &["Cache", "Code Cache"]),
+    ("BraveSoftware/Brave-Browser", &["Cache", "Code Cache"]),
+    ("microsoft-edge", &["Cache", "Code Cache"]),
+];
+
+/// A file or directory that can go without the user losing anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JunkFile {
+    pub path: PathBuf,
+    /// Size of the file, or of everything below the directory.
+    pub size: u64,
+    pub category: JunkFiles,
+}
+
+impl JunkFile {
+    fn new(path: PathBuf, category: JunkFiles) -> Self {
+        JunkFile {
+            size: size_of(&path),
+            path,
+            category,
+        }
+    }
+
+    /// Get rid of the junk. Installers, temporary files and leftovers of applications
+    /// are moved to the Trash in case they are still wanted, and their path in it
+    /// is returned; the rest is removed for good.
+    pub fn clean(&self) -> io::Result<Option<PathBuf>> {
+        match self.category {
+            JunkFiles::RandomExe | JunkFiles::TempFiles | JunkFiles::SoftwareLeftovers => {
+                FileOperation::Trash.apply(&self.path).map(Some)
+            }
+            JunkFiles::DeletedFiles => match Trash::of(&self.path) {
+                Some(trash) => trash.erase(&self.path).map(|_| None),
+                None => remove_path(&self.path).map(|_| None),
+            },
+            _ => remove_path(&self.path).map(|_| None),
+        }
+    }
+}
+
+/// Where the rules look for junk, the directories of the current user unless
+/// a test points them elsewhere.
+#[derive(Debug, Clone)]
+pub struct JunkPlaces {
+    pub home: PathBuf,
+    pub cache_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub temp_dir: PathBuf,
+    pub download_dir: Option<PathBuf>,
+    /// Directories analysed for the user, searched for rotated logs.
+    pub roots: Vec<PathBuf>,
+    /// Only temporary files of this user are junk, the others may be in use.
+    pub uid: u32,
+    /// Directories holding the installed Flatpak applications.
+    pub flatpak_dirs: Vec<PathBuf>,
+}
+
+impl JunkPlaces {
+    /// The places of the current user, `None` without a home directory.
+    pub fn of_user(roots: &[PathBuf]) -> Option<Self> {
+        let home = dirs::home_dir()?;
+        let data_dir = dirs::data_dir().unwrap_or_else(|| home.join(".local/share"));
+        Some(JunkPlaces {
+            cache_dir: dirs::cache_dir().unwrap_or_else(|| home.join(".cache")),
+            temp_dir: std::env::temp_dir(),
+            download_dir: dirs::download_dir(),
+            roots: roots.to_vec(),
+            uid: fs::metadata(&home).map(|metadata| metadata.uid()).ok()?,
+            flatpak_dirs: vec![
+                data_dir.join("flatpak/app"),
+                PathBuf::from("/var/lib/flatpak/app"),
+            ],
+            data_dir,
+            home,
+        })
+    }
+}
+
+/// A way of finding one category of junk.
+pub trait JunkRule: Send {
+    fn category(&self) -> JunkFiles;
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile>;
+}
+
+/// Runs every rule it holds, by default one for each category of junk.
+pub struct JunkDetector {
+    rules: Vec<Box<dyn JunkRule>>,
+}
+
+impl Default for JunkDetector {
+    fn default() -> Self {
+        JunkDetector::new(vec![
+            Box::new(InstallersRule),
+            Box::new(TrashRule),
+            Box::new(TempFilesRule),
+            Box::new(BrowserCacheRule),
+            Box::new(RotatedLogsRule),
+            Box::new(ThumbnailsRule),
+            Box::new(LeftoversRule),
+        ])
+    }
+}
+
+impl JunkDetector {
+    pub fn new(rules: Vec<Box<dyn JunkRule>>) -> Self {
+        JunkDetector { rules }
+    }
+
+    /// The junk found by every rule, each path once even if several rules find it.
+    pub fn detect(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        let mut seen = HashSet::new();
+        self.rules
+            .iter()
+            .flat_map(|rule| rule.find(places))
+            .filter(|junk| seen.insert(junk.path.clone()))
+            .collect()
+    }
+}
+
+/// Installers left in Downloads.
+pub struct InstallersRule;
+
+impl JunkRule for InstallersRule {
+    fn category(&self) -> JunkFiles {
+        JunkFiles::RandomExe
+    }
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        let Some(download_dir) = &places.download_dir else {
+            return Vec::new();
+        };
+        entries(download_dir)
+            .filter(|path| path.is_file())
+            .filter(|path| {
+                let extension = path
+                    .extension()
+                    .and_then(std::ffi::OsStr::to_str)
+                    .unwrap_or("")
+                    .to_lowercase();
+                INSTALLER_EXTENSIONS.contains(&extension.as_str())
+            })
+            .map(|path| JunkFile::new(path, self.category()))
+            .collect()
+    }
+}
+
+/// Files in the Trash, gone once it is emptied.
+pub struct TrashRule;
+
+impl JunkRule for TrashRule {
+    fn category(&self) -> JunkFiles {
+        JunkFiles::DeletedFiles
+    }
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        entries(&Trash::new(places.data_dir.join("Trash")).files_dir())
+            .map(|path| JunkFile::new(path, self.category()))
+            .collect()
+    }
+}
+
+/// Files of the user in the temporary directory, once they are a day old.
+/// Directories are left alone: programs keep sockets and lock files in them
+/// that are still in use however old the directory is.
+pub struct TempFilesRule;
+
+impl JunkRule for TempFilesRule {
+    fn category(&self) -> JunkFiles {
+        JunkFiles::TempFiles
+    }
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        let now = SystemTime::now();
+        entries(&places.temp_dir)
+            .filter(|path| {
+                let Ok(metadata) = fs::symlink_metadata(path) else {
+                    return false;
+                };
+                let old = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .is_some_and(|age| age >= TEMP_FILE_MIN_AGE);
+                metadata.is_file() && metadata.uid() == places.uid && old
+            })
+            .map(|path| JunkFile::new(path, self.category()))
+            .collect()
+    }
+}
+
+/// Caches of web browsers, filled again as pages are visited.
+pub struct BrowserCacheRule;
+
+impl JunkRule for BrowserCacheRule {
+    fn category(&self) -> JunkFiles {
+        JunkFiles::TempInternetFiles
+    }
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        BROWSER_CACHES
+            .iter()
+            .flat_map(|(browser, caches)| {
+                entries(&places.cache_dir.join(browser))
+                    .flat_map(move |profile| caches.iter().map(move |cache| profile.join(cache)))
+            })
+            .filter(|cache| cache.is_dir())
+            .map(|cache| JunkFile::new(cache, self.category()))
+            .collect()
+    }
+}
+
+/// Logs rotated away, like `app.log.1` or `app.log.2.gz`.
+pub struct RotatedLogsRule;
+
+impl JunkRule for RotatedLogsRule {
+    fn category(&self) -> JunkFiles {
+        JunkFiles::Logs
+    }
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        let state_dir = places.home.join(".local/state");
+        places
+            .roots
+            .iter()
+            .chain([&places.cache_dir, &state_dir])
+            .flat_map(|dir| WalkDir::new(dir).follow_links(false).into_iter().flatten())
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| is_rotated_log(&entry.file_name().to_string_lossy()))
+            .map(|entry| JunkFile::new(entry.into_path(), self.category()))
+            .collect()
+    }
+}
+
+/// Whether a file name is the one of a log rotated away.
+fn is_rotated_log(name: &str) -> bool {
+    let Some((_, rotation)) = name.rsplit_once(".log.") else {
+        return false;
+    };
+    let rotation = ["gz", "xz", "bz2", "zst"]
+        .iter()
+        .find_map(|extension| rotation.strip_suffix(&format!(".{}", extension)))
+        .unwrap_or(rotation);
+    rotation == "old" || (!rotation.is_empty() && rotation.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Thumbnails of pictures and videos, made again when they are needed.
+pub struct ThumbnailsRule;
+
+impl JunkRule for ThumbnailsRule {
+    fn category(&self) -> JunkFiles {
+        JunkFiles::Thumbnails
+    }
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        entries(&places.cache_dir.join("thumbnails"))
+            .map(|path| JunkFile::new(path, self.category()))
+            .collect()
+    }
+}
+
+/// Data left by Flatpak applications that were uninstalled. Flatpak keeps the
+/// data of every application in its own directory, so what it leaves behind is
+/// known for sure; the files of other packages could belong to anything.
+pub struct LeftoversRule;
+
+impl JunkRule for LeftoversRule {
+    fn category(&self) -> JunkFiles {
+        JunkFiles::SoftwareLeftovers
+    }
+
+    fn find(&self, places: &JunkPlaces) -> Vec<JunkFile> {
+        entries(&places.home.join(".var/app"))
+            .filter(|path| {
+                let app_id = path.file_name().unwrap_or_default();
+                !places
+                    .flatpak_dirs
+                    .iter()
+                    .any(|dir| dir.join(app_id).exists())
+            })
+            .map(|path| JunkFile::new(path, self.category()))
+            .collect()
+    }
+}
+
+/// The entries of a directory, none if it cannot be read.
+fn entries(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+}
+
+/// Size of a file, or of every file below a directory.
+fn size_of(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    #[test]
+    fn test_rotated_logs() {
+        assert!(is_rotated_log("syslog.log.1"));
+        assert!(is_rotated_log("app.log.12.gz"));
+        assert!(is_rotated_log("app.log.old"));
+        assert!(!is_rotated_log("app.log"));
+        assert!(!is_rotated_log("app.log.gz"));
+        assert!(!is_rotated_log("catalog.login.txt"));
+    }
+
+    #[test]
+    fn test_detect_junk() {
+        let base = TestDir::new("detect_junk");
+        let home = base.join("home");
+        let mut places = JunkPlaces {
+            cache_dir: home.join(".cache"),
+            data_dir: home.join(".local/share"),
+            temp_dir: base.join("tmp"),
+            download_dir: Some(home.join("Downloads")),
+            roots: vec![home.join("Documents")],
+            uid: 0,
+            flatpak_dirs: vec![home.join(".local/share/flatpak/app")],
+            home: home.clone(),
+        };
+        let files = [
+            "Downloads/setup.EXE",
+            "Downloads/photo.jpg",
+            ".local/share/Trash/files/old.txt",
+            ".cache/mozilla/firefox/abc.default/cache2/entry",
+            ".cache/thumbnails/normal/1234.png",
+            "Documents/server.log.1",
+            "Documents/server.log",
+            ".var/app/org.removed.App/config/settings",
+            ".var/app/org.installed.App/config/settings",
+            ".local/share/flatpak/app/org.installed.App/current",
+            // configuration cannot be told apart from the one of a package still used
+            ".config/removed-package/config",
+        ];
+        for file in files {
+            let path = home.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "junk").unwrap();
+        }
+        fs::create_dir_all(&places.temp_dir).unwrap();
+        // still in use, it was written a moment ago
+        fs::write(places.temp_dir.join("fresh.tmp"), "junk").unwrap();
+        places.uid = fs::metadata(places.temp_dir.join("fresh.tmp"))
+            .unwrap()
+            .uid();
+
+        let mut junk = JunkDetector::default().detect(&places);
+        junk.sort_by(|a, b| a.path.cmp(&b.path));
+        let found: Vec<(PathBuf, JunkFiles)> = junk
+            .iter()
+            .map(|junk| {
+                (
+                    junk.path.strip_prefix(&home).unwrap().to_path_buf(),
+                    junk.category,
+                )
+            })
+            .collect();
+        let expected: Vec<(PathBuf, JunkFiles)> = [
+            (
+                ".cache/mozilla/firefox/abc.default/cache2",
+                JunkFiles::TempInternetFiles,
+            ),
+            (".cache/thumbnails/normal", JunkFiles::Thumbnails),
+            (".local/share/Trash/files/old.txt", JunkFiles::DeletedFiles),
+            (".var/app/org.removed.App", JunkFiles::SoftwareLeftovers),
+            ("Documents/server.log.1", JunkFiles::Logs),
+            ("Downloads/setup.EXE", JunkFiles::RandomExe),
+        ]
+        .into_iter()
+        .map(|(path, category)| (PathBuf::from(path), category))
+        .collect();
+        assert_eq!(found, expected);
+        assert!(junk.iter().all(|junk| junk.size == 4));
+
+        // old temporary files are junk, old directories may still hold sockets in use
+        let two_days_ago = SystemTime::now() - 2 * TEMP_FILE_MIN_AGE;
+        let old_file = places.temp_dir.join("old.tmp");
+        let old_dir = places.temp_dir.join("app-socket-dir");
+        fs::write(&old_file, "junk").unwrap();
+        fs::create_dir(&old_dir).unwrap();
+        for path in [&old_file, &old_dir] {
+            fs::File::open(path)
+                .unwrap()
+                .set_modified(two_days_ago)
+                .unwrap();
+        }
+        let temp_files: Vec<PathBuf> = TempFilesRule
+            .find(&places)
+            .into_iter()
+            .map(|junk| junk.path)
+            .collect();
+        assert_eq!(temp_files, vec![old_file]);
+
+        // emptying the Trash removes the info of the file too
+        let trashed = &junk[2];
+        let info = home.join(".local/share/Trash/info/old.txt.trashinfo");
+        fs::create_dir_all(info.parent().unwrap()).unwrap();
+        fs::write(&info, "[Trash Info]\n").unwrap();
+        assert_eq!(trashed.clean().unwrap(), None);
+        assert!(!trashed.path.exists());
+        assert!(!info.exists());
+    }
+}
@@ -0,0 +1,152 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::duplicates::DupFile;
+
+/// A preference for which copy of a group of duplicates to keep.
+///
+/// Rules are composed in order: the first rule that tells two copies apart
+/// decides between them, and the later ones only break its ties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepRule {
+    /// Keep the most recently created copy.
+    Newest,
+    /// Keep the earliest created copy.
+    Oldest,
+    /// Keep the copy with the shortest path, usually the best organised one.
+    ShortestPath,
+    /// Keep a copy that is outside the Downloads directory.
+    NotInDownloads,
+    /// Keep a copy that is inside the given directory.
+    PreferredDirectory(PathBuf),
+}
+
+impl KeepRule {
+    /// Parse a rule as stored in the settings, `None` if it is unknown.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "newest" => Some(KeepRule::Newest),
+            "oldest" => Some(KeepRule::Oldest),
+            "shortest-path" => Some(KeepRule::ShortestPath),
+            "not-in-downloads" => Some(KeepRule::NotInDownloads),
+            _ => value
+                .strip_prefix("preferred-directory:")
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| KeepRule::PreferredDirectory(PathBuf::from(dir))),
+        }
+    }
+
+    /// Compare two copies, `Ordering::Less` meaning `a` is the better one to keep.
+    fn compare(&self, a: &DupFile, b: &DupFile) -> Ordering {
+        match self {
+            KeepRule::Newest => b.date_created.cmp(&a.date_created),
+            KeepRule::Oldest => a.date_created.cmp(&b.date_created),
+            KeepRule::ShortestPath => a
+                .file_path
+                .as_os_str()
+                .len()
+                .cmp(&b.file_path.as_os_str().len()),
+            KeepRule::NotInDownloads => match dirs::download_dir() {
+                Some(downloads) => is_in(a, &downloads).cmp(&is_in(b, &downloads)),
+                None => Ordering::Equal,
+            },
+            KeepRule::PreferredDirectory(dir) => is_in(b, dir).cmp(&is_in(a, dir)),
+        }
+    }
+
+    /// A short description for the user.
+    pub fn description(&self) -> String {
+        match self {
+            KeepRule::Newest => "newest".to_string(),
+            KeepRule::Oldest => "oldest".to_string(),
+            KeepRule::ShortestPath => "shortest path".to_string(),
+            KeepRule::NotInDownloads => "not in Downloads".to_string(),
+            KeepRule::PreferredDirectory(dir) => format!("in {}", dir.display()),
+        }
+    }
+}
+
+impl fmt::Display for KeepRule {
+    /// The rule as stored in the settings.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeepRule::Newest => write!(f, "newest"),
+            KeepRule::Oldest => write!(f, "oldest"),
+            KeepRule::ShortestPath => write!(f, "shortest-path"),
+            KeepRule::NotInDownloads => write!(f, "not-in-downloads"),
+            KeepRule::PreferredDirectory(dir) => {
+                write!(f, "preferred-directory:{}", dir.display())
+            }
+        }
+    }
+}
+
+fn is_in(file: &DupFile, dir: &Path) -> bool {
+    file.file_path.starts_with(dir)
+}
+
+/// Index of the copy to keep in a group, by applying `rules` in order. Copies the
+/// rules cannot tell apart are kept in their order, so the first one wins.
+pub fn choose_keeper(files: &[DupFile], rules: &[KeepRule]) -> Option<usize> {
+    (0..files.len()).min_by(|&a, &b| {
+        rules
+            .iter()
+            .map(|rule| rule.compare(&files[a], &files[b]))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn file(path: &str, year: i32) -> DupFile {
+        let date = Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap();
+        DupFile {
+            date_created: date,
+            date_modified: date,
+            ..DupFile::fake(path, 1)
+        }
+    }
+
+    #[test]
+    fn test_rules_round_trip() {
+        let rules = [
+            KeepRule::Newest,
+            KeepRule::Oldest,
+            KeepRule::ShortestPath,
+            KeepRule::NotInDownloads,
+            KeepRule::PreferredDirectory(PathBuf::from("/home/user/Pictures")),
+        ];
+        for rule in rules {
+            assert_eq!(KeepRule::parse(&rule.to_string()), Some(rule));
+        }
+        assert_eq!(KeepRule::parse("largest"), None);
+    }
+
+    #[test]
+    fn test_choose_keeper() {
+        let files = [
+            file("/home/user/Documents/archive/2020/report.txt", 2020),
+            file("/home/user/Pictures/report.txt", 2020),
+            file("/home/user/report.txt", 2023),
+        ];
+
+        assert_eq!(choose_keeper(&files, &[KeepRule::Newest]), Some(2));
+        // ties on the first rule are broken by the next one
+        assert_eq!(
+            choose_keeper(&files, &[KeepRule::Oldest, KeepRule::ShortestPath]),
+            Some(1)
+        );
+        let preferred = KeepRule::PreferredDirectory(PathBuf::from("/home/user/Documents"));
+        assert_eq!(
+            choose_keeper(&files, &[preferred, KeepRule::Newest]),
+            Some(0)
+        );
+        assert_eq!(choose_keeper(&files, &[]), Some(0));
+        assert_eq!(choose_keeper(&[], &[KeepRule::Newest]), None);
+    }
+}
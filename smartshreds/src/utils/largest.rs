@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+
+use super::analysis::FileType;
+
+/// How many of the largest files and folders are kept by default.
+pub const DEFAULT_LARGEST_COUNT: usize = 50;
+
+/// A file or folder among the largest ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LargeEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// `None` for a folder.
+    pub file_type: Option<FileType>,
+    pub accessed: DateTime<Utc>,
+}
+
+impl Ord for LargeEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.size
+            .cmp(&other.size)
+            .then_with(|| other.path.cmp(&self.path))
+    }
+}
+
+impl PartialOrd for LargeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The largest entries seen so far, at most `limit` of them and none smaller
+/// than `min_size`. The smallest one is dropped when a larger one comes in.
+#[derive(Debug, Clone)]
+pub struct Largest {
+    limit: usize,
+    min_size: u64,
+    heap: BinaryHeap<Reverse<LargeEntry>>,
+}
+
+impl Default for Largest {
+    fn default() -> Self {
+        Largest::new(DEFAULT_LARGEST_COUNT, 0)
+    }
+}
+
+impl Largest {
+    pub fn new(limit: usize, min_size: u64) -> Self {
+        Largest {
+            limit,
+            min_size,
+            heap: BinaryHeap::with_capacity(limit + 1),
+        }
+    }
+
+    pub fn min_size(&self) -> u64 {
+        self.min_size
+    }
+
+    /// Whether an entry of `size` would be kept, checked before building it.
+    pub fn accepts(&self, size: u64) -> bool {
+        if size < self.min_size || self.limit == 0 {
+            return false;
+        }
+        match self.heap.peek() {
+            Some(Reverse(smallest)) if self.heap.len() >= self.limit => size > smallest.size,
+            _ => true,
+        }
+    }
+
+    pub fn push(&mut self, entry: LargeEntry) {
+        if !self.accepts(entry.size) {
+            return;
+        }
+        self.heap.push(Reverse(entry));
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+        }
+    }
+
+    /// The entries kept, largest first.
+    pub fn sorted(&self) -> Vec<LargeEntry> {
+        let mut entries: Vec<LargeEntry> = self
+            .heap
+            .iter()
+            .map(|Reverse(entry)| entry.clone())
+            .collect();
+        entries.sort_by(|a, b| b.cmp(a));
+        entries
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(name: &str, size: u64) -> LargeEntry {
+        LargeEntry {
+            path: PathBuf::from(name),
+            size,
+            file_type: Some(FileType::Other),
+            accessed: DateTime::<Utc>::default(),
+        }
+    }
+
+    #[test]
+    fn test_largest() {
+        let mut largest = Largest::new(3, 10);
+        for (name, size) in [
+            ("a", 50),
+            ("b", 5),
+            ("c", 20),
+            ("d", 70),
+            ("e", 30),
+            ("f", 10),
+        ] {
+            largest.push(entry(name, size));
+        }
+
+        let sizes: Vec<u64> = largest.sorted().iter().map(|entry| entry.size).collect();
+        assert_eq!(sizes, vec![70, 50, 30]);
+        assert!(!largest.accepts(30));
+        assert!(largest.accepts(31));
+        assert!(!Largest::new(3, 10).accepts(9));
+        assert!(!Largest::new(0, 0).accepts(100));
+    }
+}
@@ -0,0 +1,568 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// How much of a file is read to recognise it, enough for the `ustar` mark of tar.
+const HEADER_SIZE: usize = 512;
+/// The type of content nothing is known about.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Magic bytes at an offset of a file, and the MIME type they mark. Magic short
+/// enough to start a text file is checked with the header it belongs to in `sniff`.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"II*\x00", "image/tiff"),
+    (0, b"MM\x00*", "image/tiff"),
+    (0, b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+    (0, b"8BPS", "image/vnd.adobe.photoshop"),
+    (0, b"gimp xcf", "image/x-xcf"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"{\\rtf", "application/rtf"),
+    (0, b"\xff\xfb", "audio/mpeg"),
+    (0, b"\xff\xf3", "audio/mpeg"),
+    (0, b"\xff\xf2", "audio/mpeg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"\x30\x26\xb2\x75\x8e\x66\xcf\x11", "video/x-ms-asf"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"Rar!\x1a\x07", "application/vnd.rar"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"\x7fELF", "application/x-executable"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+];
+
+/// Formats that hold many kinds of content: the name of the file tells better what it is.
+const CONTAINERS: &[&str] = &[
+    "application/zip",
+    "application/x-ole-storage",
+    "application/ogg",
+    "video/x-matroska",
+    "text/plain",
+];
+
+/// Extensions recognised when shared-mime-info is missing or does not know them.
+const FALLBACK_GLOBS: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("bmp", "image/bmp"),
+    ("webp", "image/webp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("heic", "image/heif"),
+    ("psd", "image/vnd.adobe.photoshop"),
+    ("xcf", "image/x-xcf"),
+    ("eps", "image/x-eps"),
+    ("svg", "image/svg+xml"),
+    ("svgz", "image/svg+xml-compressed"),
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("mkv", "video/x-matroska"),
+    ("webm", "video/webm"),
+    ("avi", "video/x-msvideo"),
+    ("flv", "video/x-flv"),
+    ("wmv", "video/x-ms-wmv"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/x-wav"),
+    ("flac", "audio/flac"),
+    ("aac", "audio/aac"),
+    ("ogg", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("m4a", "audio/mp4"),
+    ("pdf", "application/pdf"),
+    ("doc", "application/msword"),
+    ("xls", "application/vnd.ms-excel"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+    ("epub", "application/epub+zip"),
+    ("rtf", "application/rtf"),
+    ("txt", "text/plain"),
+    ("log", "text/x-log"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("ts", "text/x-typescript"),
+    ("py", "text/x-python"),
+    ("go", "text/x-go"),
+    ("java", "text/x-java"),
+    ("c", "text/x-c"),
+    ("h", "text/x-chdr"),
+    ("cpp", "text/x-c++src"),
+    ("hpp", "text/x-c++hdr"),
+    ("rs", "text/rust"),
+    ("sh", "application/x-shellscript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/x-yaml"),
+    ("yml", "application/x-yaml"),
+    ("toml", "application/toml"),
+    ("ini", "text/x-ini"),
+    ("zip", "application/zip"),
+];
+
+/// The broad kind of content of a MIME type. The dashboard categories, the
+/// duplicate filters and the previews are all derived from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Image,
+    Video,
+    Audio,
+    Document,
+    Other,
+}
+
+impl From<&str> for ContentKind {
+    fn from(mime_type: &str) -> Self {
+        let (top_level, subtype) = mime_type.split_once('/').unwrap_or((mime_type, ""));
+        match top_level {
+            "image" => ContentKind::Image,
+            "video" => ContentKind::Video,
+            "audio" => ContentKind::Audio,
+            "text" => ContentKind::Document,
+            "application" if is_document(subtype) => ContentKind::Document,
+            _ => ContentKind::Other,
+        }
+    }
+}
+
+fn is_document(subtype: &str) -> bool {
+    matches!(
+        subtype,
+        "pdf"
+            | "rtf"
+            | "msword"
+            | "vnd.ms-excel"
+            | "vnd.ms-powerpoint"
+            | "epub+zip"
+            | "json"
+            | "xml"
+            | "javascript"
+            | "x-yaml"
+            | "toml"
+            | "x-shellscript"
+    ) || subtype.starts_with("vnd.openxmlformats-officedocument.")
+        || subtype.starts_with("vnd.oasis.opendocument.")
+}
+
+/// MIME type of a file: recognised from its first bytes, else guessed from its
+/// name, `application/octet-stream` if neither tells.
+pub fn detect(path: &Path) -> String {
+    let header = read_header(path).unwrap_or_default();
+    let sniffed = sniff(&header);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match sniffed {
+        Some(mime_type) if !CONTAINERS.contains(&mime_type) => mime_type.to_string(),
+        _ => from_name(&name)
+            .or(sniffed.map(str::to_string))
+            .unwrap_or_else(|| OCTET_STREAM.to_string()),
+    }
+}
+
+fn read_header(path: &Path) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    File::open(path)?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// MIME type told by the first bytes of a file, `None` if they are not recognised.
+pub fn sniff(header: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    if let Some((_, _, mime_type)) = MAGIC.iter().find(|(offset, magic, _)| at(*offset, magic)) {
+        return Some(mime_type);
+    }
+    if let Some(mime_type) = sniff_short_magic(header) {
+        return Some(mime_type);
+    }
+    if at(0, b"RIFF") {
+        return match header.get(8..12)? {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/x-wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+    if at(4, b"ftyp") {
+        return match header.get(8..12)? {
+            b"qt  " => Some("video/quicktime"),
+            b"M4A " | b"M4B " => Some("audio/mp4"),
+            b"heic" | b"heix" | b"mif1" => Some("image/heif"),
+            _ => Some("video/mp4"),
+        };
+    }
+    if at(0, b"PK\x03\x04") {
+        return Some("application/zip");
+    }
+    if at(0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1") {
+        return Some("application/x-ole-storage");
+    }
+    if at(0, b"OggS") {
+        return Some("application/ogg");
+    }
+    if at(0, b"\x1a\x45\xdf\xa3") {
+        return Some("video/x-matroska");
+    }
+    sniff_text(header)
+}
+
+/// Formats whose magic is a few letters: they are only recognised when the
+/// rest of their header is valid, so text starting with the same letters is not.
+fn sniff_short_magic(header: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+    let u32_at = |offset: usize| {
+        header
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    // reserved fields of zero, then the size of a known DIB header
+    if at(0, b"BM")
+        && u32_at(6) == Some(0)
+        && matches!(u32_at(14), Some(12 | 40 | 52 | 56 | 64 | 108 | 124))
+    {
+        return Some("image/bmp");
+    }
+    // a major version of ID3v2, then a size made of 7-bit bytes
+    if at(0, b"ID3")
+        && matches!(header.get(3), Some(2..=4))
+        && header
+            .get(6..10)
+            .is_some_and(|size| size.iter().all(|b| *b < 0x80))
+    {
+        return Some("audio/mpeg");
+    }
+    if at(0, b"FLV\x01") && at(5, b"\x00\x00\x00\x09") {
+        return Some("video/x-flv");
+    }
+    // a block size, then the magic of the first block or of the end of the stream
+    if at(0, b"BZh")
+        && matches!(header.get(3), Some(b'1'..=b'9'))
+        && (at(4, b"\x31\x41\x59\x26\x53\x59") || at(4, b"\x17\x72\x45\x38\x50\x90"))
+    {
+        return Some("application/x-bzip2");
+    }
+    // the DOS header points to the PE header of a Windows executable
+    if at(0, b"MZ") && u32_at(0x3c).is_some_and(|offset| at(offset as usize, b"PE\x00\x00")) {
+        return Some("application/x-msdownload");
+    }
+    None
+}
+
+/// Text has no magic: it is told apart from binary content by the absence of
+/// control characters and by being valid UTF-8.
+fn sniff_text(header: &[u8]) -> Option<&'static str> {
+    if header.is_empty() || header.contains(&0) {
+        return None;
+    }
+    let text = match std::str::from_utf8(header) {
+        Ok(text) => text,
+        // the header may end in the middle of a character
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&header[..e.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    let start = text.trim_start();
+    if start.starts_with("<svg") || (start.starts_with("<?xml") && text.contains("<svg")) {
+        Some("image/svg+xml")
+    } else if start.starts_with("<?xml") {
+        Some("application/xml")
+    } else if start.starts_with("#!") {
+        Some("application/x-shellscript")
+    } else {
+        Some("text/plain")
+    }
+}
+
+/// MIME type guessed from a file name with the globs of shared-mime-info.
+pub fn from_name(name: &str) -> Option<String> {
+    globs().matching(name)
+}
+
+/// Globs of the shared-mime-info databases, loaded once.
+fn globs() -> &'static Globs {
+    static GLOBS: OnceLock<Globs> = OnceLock::new();
+    GLOBS.get_or_init(|| {
+        let mut globs = Globs::default();
+        for dir in mime_dirs() {
+            if let Ok(contents) = std::fs::read_to_string(dir.join("globs2")) {
+                globs.parse(&contents);
+            }
+        }
+        globs
+    })
+}
+
+/// The `mime` directories of the XDG data directories, the one of the user first.
+fn mime_dirs() -> Vec<PathBuf> {
+    let data_dirs =
+        std::env::var("XDG_DATA_DIRS").unwrap_or_else(|_| "/usr/local/share:/usr/share".into());
+    dirs::data_dir()
+        .into_iter()
+        .chain(std::env::split_paths(&data_dirs))
+        .map(|dir| dir.join("mime"))
+        .collect()
+}
+
+/// A pattern of a `globs2` file, lowercased once unless it is case sensitive.
+#[derive(Debug)]
+struct Glob {
+    /// Position in the databases, the later of two equal patterns wins.
+    order: usize,
+    weight: u32,
+    pattern: String,
+    mime_type: String,
+    case_sensitive: bool,
+}
+
+impl Glob {
+    /// Patterns win by weight, then by length.
+    fn rank(&self) -> (u32, usize, usize) {
+        (self.weight, self.pattern.len(), self.order)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Globs {
+    /// The patterns read so far, so a later database does not override them.
+    seen: HashSet<String>,
+    /// Patterns like `*.tar.gz`, by what follows their `*.` in lowercase. Most
+    /// patterns are like these, a name only has to be looked up for each of its
+    /// extensions.
+    extensions: HashMap<String, Vec<Glob>>,
+    /// The other patterns, in the order they were read.
+    patterns: Vec<Glob>,
+}
+
+impl Globs {
+    /// Read the lines of a `globs2` file, `weight:type:pattern[:flags]`.
+    fn parse(&mut self, contents: &str) {
+        for line in contents.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.split(':');
+            let (Some(weight), Some(mime_type), Some(pattern)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let case_sensitive = fields.next().is_some_and(|flags| flags.contains("cs"));
+            let Ok(weight) = weight.parse() else {
+                continue;
+            };
+            // a user database read first takes precedence over the system one
+            if !self.seen.insert(pattern.to_string()) {
+                continue;
+            }
+            let glob = Glob {
+                order: self.seen.len(),
+                weight,
+                pattern: if case_sensitive {
+                    pattern.to_string()
+                } else {
+                    pattern.to_lowercase()
+                },
+                mime_type: mime_type.to_string(),
+                case_sensitive,
+            };
+            match literal_extension(&glob.pattern) {
+                Some(extension) => self
+                    .extensions
+                    .entry(extension.to_lowercase())
+                    .or_default()
+                    .push(glob),
+                None => self.patterns.push(glob),
+            }
+        }
+    }
+
+    /// The type of the heaviest, then longest, pattern matching `name`, else the
+    /// type of its extension in the fallback table.
+    fn matching(&self, name: &str) -> Option<String> {
+        let lowercase_name = name.to_lowercase();
+        let compared = |glob: &Glob| {
+            if glob.case_sensitive {
+                name
+            } else {
+                lowercase_name.as_str()
+            }
+        };
+        // every extension of the name, `gz` and `tar.gz` for `backup.tar.gz`
+        let by_extension = lowercase_name
+            .match_indices('.')
+            .filter_map(|(dot, _)| self.extensions.get(&lowercase_name[dot + 1..]))
+            .flatten()
+            .filter(|glob| compared(glob).ends_with(&glob.pattern[1..]));
+        let matched = self
+            .patterns
+            .iter()
+            .filter(|glob| glob_match(&glob.pattern, compared(glob)))
+            .chain(by_extension)
+            .max_by_key(|glob| glob.rank())
+            .map(|glob| glob.mime_type.clone());
+
+        matched.or_else(|| {
+            let (_, extension) = lowercase_name.rsplit_once('.')?;
+            FALLBACK_GLOBS
+                .iter()
+                .find(|(known, _)| *known == extension)
+                .map(|(_, mime_type)| mime_type.to_string())
+        })
+    }
+}
+
+/// What follows the `*.` of a pattern that matches a literal extension, like `*.tar.gz`.
+fn literal_extension(pattern: &str) -> Option<&str> {
+    let extension = pattern.strip_prefix("*.")?;
+    (!extension.is_empty() && !extension.contains(['*', '?', '['])).then_some(extension)
+}
+
+/// Whether `name` matches a glob made of `*`, `?`, `[...]` and literal characters.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    glob_match_from(&pattern, &name)
+}
+
+fn glob_match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| glob_match_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && glob_match_from(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(end) = pattern.iter().skip(1).position(|&c| c == ']') else {
+                return false;
+            };
+            let set = &pattern[1..end + 1];
+            let (negated, set) = match set.first() {
+                Some('!') => (true, &set[1..]),
+                _ => (false, set),
+            };
+            let Some(&c) = name.first() else {
+                return false;
+            };
+            let in_set = set
+                .windows(3)
+                .any(|range| range[1] == '-' && range[0] <= c && c <= range[2])
+                || set.contains(&c);
+            in_set != negated && glob_match_from(&pattern[end + 2..], &name[1..])
+        }
+        Some(&literal) => {
+            name.first() == Some(&literal) && glob_match_from(&pattern[1..], &name[1..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00"), Some("image/png"));
+        assert_eq!(sniff(b"\x00\x00\x00\x18ftypisom"), Some("video/mp4"));
+        assert_eq!(sniff(b"RIFF\x00\x00\x00\x00WAVEfmt "), Some("audio/x-wav"));
+        assert_eq!(sniff(b"%PDF-1.7\n"), Some("application/pdf"));
+        let mut bmp =
+            b"BM\x46\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00\x28\x00\x00\x00".to_vec();
+        bmp.extend([0; 32]);
+        assert_eq!(sniff(&bmp), Some("image/bmp"));
+        let mut exe = b"MZ".to_vec();
+        exe.resize(0x3c, 0);
+        exe.extend(0x40u32.to_le_bytes());
+        exe.extend(b"PE\x00\x00");
+        assert_eq!(sniff(&exe), Some("application/x-msdownload"));
+        assert_eq!(
+            sniff(b"ID3\x04\x00\x00\x00\x00\x1f\x76"),
+            Some("audio/mpeg")
+        );
+        assert_eq!(sniff(b"ID3 tags of the album"), Some("text/plain"));
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<svg>"),
+            Some("image/svg+xml")
+        );
+        assert_eq!(sniff("caf\u{e9}".as_bytes()), Some("text/plain"));
+        // a header cut in the middle of a character is still text
+        assert_eq!(sniff(&"caf\u{e9}".as_bytes()[..4]), Some("text/plain"));
+        assert_eq!(sniff(b"\x00\x01\x02\x03"), None);
+    }
+
+    #[test]
+    fn test_globs() {
+        let mut globs = Globs::default();
+        globs.parse("# comment\n50:text/x-csrc:*.c\n50:text/x-c++src:*.C:cs\n60:text/x-readme:README*\n10:text/plain:*.[tT][xX][tT]\n");
+        globs.parse("50:application/gzip:*.gz\n50:application/x-compressed-tar:*.tar.gz\n");
+
+        assert_eq!(globs.matching("main.c"), Some("text/x-csrc".into()));
+        assert_eq!(globs.matching("main.C"), Some("text/x-c++src".into()));
+        assert_eq!(globs.matching("README.md"), Some("text/x-readme".into()));
+        assert_eq!(globs.matching("notes.TXT"), Some("text/plain".into()));
+        // the longest extension wins
+        assert_eq!(
+            globs.matching("backup.TAR.gz"),
+            Some("application/x-compressed-tar".into())
+        );
+        assert_eq!(globs.matching("log.gz"), Some("application/gzip".into()));
+        // extensions unknown to the database come from the fallback table
+        assert_eq!(globs.matching("photo.JPG"), Some("image/jpeg".into()));
+        assert_eq!(globs.matching("no_extension"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        let base = TestDir::new("detect_mime_type");
+        // content wins over a misleading name, names refine generic content
+        fs::write(base.join("photo.txt"), b"\xff\xd8\xff\xe0\x00\x10JFIF").unwrap();
+        fs::write(base.join("report"), b"%PDF-1.4\n").unwrap();
+        fs::write(base.join("notes"), "plain words").unwrap();
+        fs::write(base.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(base.join("data.bin"), b"\x00\x01\x02").unwrap();
+        // text starting like a format with a short magic is still text
+        fs::write(base.join("cars.txt"), "BMW service every 30000 km").unwrap();
+        fs::write(base.join("zones"), "MZ is the code of Mozambique").unwrap();
+
+        assert_eq!(detect(&base.join("photo.txt")), "image/jpeg");
+        assert_eq!(detect(&base.join("report")), "application/pdf");
+        assert_eq!(detect(&base.join("notes")), "text/plain");
+        assert_eq!(detect(&base.join("cars.txt")), "text/plain");
+        assert_eq!(detect(&base.join("zones")), "text/plain");
+        assert_eq!(
+            ContentKind::from(detect(&base.join("main.rs")).as_str()),
+            ContentKind::Document
+        );
+        assert_eq!(
+            ContentKind::from(detect(&base.join("data.bin")).as_str()),
+            ContentKind::Other
+        );
+    }
+}
@@ -1,8 +1,25 @@
 pub mod analysis;
 pub mod recents;
 pub mod auth;
+pub mod dir_tree;
+pub mod disks;
 pub mod duplicates;
+pub mod file_ops;
+pub mod hash_cache;
+pub mod history;
+pub mod journal;
+pub mod junk;
+pub mod keep_rules;
+pub mod largest;
+pub mod mime_type;
+pub mod perceptual;
 pub mod preview;
+pub mod reflink;
+pub mod shred;
+pub mod stale;
+#[cfg(test)]
+pub mod test_dir;
+pub mod text_similarity;
 
 use std::sync::OnceLock;
 use tokio::runtime::Runtime;
@@ -0,0 +1,237 @@
+use gdk_pixbuf::Pixbuf;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::path::Path;
+
+use super::duplicates::{connected_groups, DupFile, SimilarFile};
+
+/// Side of the grid that every perceptual hash is computed from.
+const HASH_SIDE: usize = 8;
+/// Side of the image the DCT of `PerceptualHash::Dct` is computed over.
+const DCT_SIDE: usize = 32;
+
+/// The algorithms used to find images that look alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PerceptualHash {
+    /// aHash: which pixels are brighter than the mean.
+    Average,
+    /// dHash: in which direction the brightness changes between neighbours.
+    #[default]
+    Difference,
+    /// pHash: which low frequencies of the DCT are above their median.
+    Dct,
+}
+
+impl From<&str> for PerceptualHash {
+    fn from(value: &str) -> Self {
+        match value {
+            "ahash" => PerceptualHash::Average,
+            "phash" => PerceptualHash::Dct,
+            _ => PerceptualHash::Difference,
+        }
+    }
+}
+
+/// How near-duplicate images are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageSimilarity {
+    pub algorithm: PerceptualHash,
+    /// Largest Hamming distance between two hashes for their images to be similar.
+    pub max_distance: u32,
+}
+
+impl Default for ImageSimilarity {
+    fn default() -> Self {
+        ImageSimilarity {
+            algorithm: PerceptualHash::default(),
+            max_distance: 8,
+        }
+    }
+}
+
+impl PerceptualHash {
+    /// Hash an image, or `None` if it cannot be decoded.
+    pub fn hash_image(&self, path: &Path) -> Option<u64> {
+        match self {
+            PerceptualHash::Average => {
+                load_grayscale(path, HASH_SIDE, HASH_SIDE).map(|pixels| average_hash(&pixels))
+            }
+            PerceptualHash::Difference => load_grayscale(path, HASH_SIDE + 1, HASH_SIDE)
+                .map(|pixels| difference_hash(&pixels)),
+            PerceptualHash::Dct => {
+                load_grayscale(path, DCT_SIDE, DCT_SIDE).map(|pixels| dct_hash(&pixels))
+            }
+        }
+    }
+}
+
+/// Decode an image scaled to `width` x `height` and return the luminance of its
+/// pixels, row by row.
+fn load_grayscale(path: &Path, width: usize, height: usize) -> Option<Vec<f64>> {
+    let pixbuf = match Pixbuf::from_file_at_scale(path, width as i32, height as i32, false) {
+        Ok(pixbuf) => pixbuf,
+        Err(e) => {
+            eprintln!("Error decoding image {:?}: {}", path, e);
+            return None;
+        }
+    };
+    if pixbuf.width() as usize != width || pixbuf.height() as usize != height {
+        return None;
+    }
+
+    let bytes = pixbuf.read_pixel_bytes();
+    let rowstride = pixbuf.rowstride() as usize;
+    let channels = pixbuf.n_channels() as usize;
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let offset = y * rowstride + x * channels;
+            let rgb = bytes.get(offset..offset + 3)?;
+            pixels.push(0.299 * rgb[0] as f64 + 0.587 * rgb[1] as f64 + 0.114 * rgb[2] as f64);
+        }
+    }
+    Some(pixels)
+}
+
+/// aHash of an 8x8 grayscale image.
+pub fn average_hash(pixels: &[f64]) -> u64 {
+    let mean = pixels.iter().sum::<f64>() / pixels.len() as f64;
+    bits_from(pixels.iter().map(|&pixel| pixel > mean))
+}
+
+/// dHash of a 9x8 grayscale image.
+pub fn difference_hash(pixels: &[f64]) -> u64 {
+    let width = HASH_SIDE + 1;
+    bits_from((0..HASH_SIDE).flat_map(|y| {
+        (0..HASH_SIDE).map(move |x| pixels[y * width + x] > pixels[y * width + x + 1])
+    }))
+}
+
+/// pHash of a 32x32 grayscale image.
+pub fn dct_hash(pixels: &[f64]) -> u64 {
+    // the DCT is separable: transform the rows, then the columns of the result
+    let mut rows = vec![0.0; DCT_SIDE * DCT_SIDE];
+    for y in 0..DCT_SIDE {
+        for u in 0..HASH_SIDE {
+            rows[y * DCT_SIDE + u] = dct_coefficient(|x| pixels[y * DCT_SIDE + x], u);
+        }
+    }
+    let mut low_frequencies = Vec::with_capacity(HASH_SIDE * HASH_SIDE);
+    for v in 0..HASH_SIDE {
+        for u in 0..HASH_SIDE {
+            low_frequencies.push(dct_coefficient(|y| rows[y * DCT_SIDE + u], v));
+        }
+    }
+
+    // the DC term only says how bright the image is, so it is left out of the median
+    let mut sorted = low_frequencies[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    bits_from(
+        low_frequencies
+            .iter()
+            .map(|&coefficient| coefficient > median),
+    )
+}
+
+/// Coefficient `k` of the DCT-II of a `DCT_SIDE` long signal.
+fn dct_coefficient(signal: impl Fn(usize) -> f64, k: usize) -> f64 {
+    (0..DCT_SIDE)
+        .map(|n| signal(n) * (PI / DCT_SIDE as f64 * (n as f64 + 0.5) * k as f64).cos())
+        .sum()
+}
+
+fn bits_from(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0, |hash, bit| (hash << 1) | bit as u64)
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Similarity between two images from the distance of their hashes, from 0 to 1.
+pub fn similarity(distance: u32) -> f64 {
+    1.0 - distance as f64 / 64.0
+}
+
+/// Cluster images whose hashes are at most `max_distance` apart.
+///
+/// Every cluster is ordered from its largest image, used as the reference the
+/// similarity of the others is measured against.
+pub fn group_similar_images(
+    images: Vec<(DupFile, u64)>,
+    max_distance: u32,
+) -> Vec<Vec<SimilarFile>> {
+    let pairs = (0..images.len()).flat_map(|i| {
+        let images = &images;
+        (i + 1..images.len())
+            .filter(move |&j| hamming_distance(images[i].1, images[j].1) <= max_distance)
+            .map(move |j| (i, j))
+    });
+    let clusters = connected_groups(images.len(), pairs);
+
+    clusters
+        .into_iter()
+        .map(|cluster| {
+            let mut cluster: Vec<&(DupFile, u64)> = cluster.iter().map(|&i| &images[i]).collect();
+            cluster.sort_by(|a, b| {
+                b.0.file_size
+                    .cmp(&a.0.file_size)
+                    .then_with(|| a.0.file_path.cmp(&b.0.file_path))
+            });
+            let reference = cluster[0].1;
+            cluster
+                .into_iter()
+                .map(|(file, hash)| SimilarFile {
+                    file: file.clone(),
+                    similarity: similarity(hamming_distance(reference, *hash)),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_difference_hash_of_gradient() {
+        let width = HASH_SIDE + 1;
+        let falling: Vec<f64> = (0..width * HASH_SIDE)
+            .map(|i| (width - i % width) as f64)
+            .collect();
+        let rising: Vec<f64> = (0..width * HASH_SIDE).map(|i| (i % width) as f64).collect();
+
+        assert_eq!(difference_hash(&falling), u64::MAX);
+        assert_eq!(difference_hash(&rising), 0);
+    }
+
+    #[test]
+    fn test_hashes_survive_brightness_changes() {
+        let pixels: Vec<f64> = (0..DCT_SIDE * DCT_SIDE)
+            .map(|i| ((i * 37) % 101) as f64)
+            .collect();
+        let brighter: Vec<f64> = pixels.iter().map(|pixel| pixel * 1.1 + 5.0).collect();
+
+        assert_eq!(dct_hash(&pixels), dct_hash(&brighter));
+        assert_eq!(average_hash(&pixels[..64]), average_hash(&brighter[..64]));
+    }
+
+    #[test]
+    fn test_group_similar_images() {
+        let groups = group_similar_images(
+            vec![
+                (DupFile::fake("small.jpg", 10), 0b1111),
+                (DupFile::fake("large.jpg", 100), 0b1110),
+                (DupFile::fake("other.jpg", 100), u64::MAX),
+            ],
+            2,
+        );
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0][0].file.file_name, "large.jpg");
+        assert_eq!(groups[0][0].similarity, 1.0);
+        assert_eq!(groups[0][1].similarity, similarity(1));
+    }
+}
@@ -3,6 +3,8 @@ use gtk::{cairo, gio, DrawingArea, Image, MediaFile, TextView, Video, Widget};
 use rsvg;
 use std::{fs::File, io::Read, path::PathBuf};
 
+use super::mime_type::{self, ContentKind};
+
 pub enum PreviewFileType {
     Image,
     Svg,
@@ -19,22 +21,17 @@ impl Default for PreviewFileType {
 }
 
 impl From<&str> for PreviewFileType {
-    fn from(value: &str) -> Self {
-        match value {
-            "mp4" | "mkv" | "avi" | "webm" | "mov" => Self::Video,
-            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "ico" | "tiff" | "tif" | "svgz"
-            | "eps" | "psd" | "ai" | "xcf" | "psb" | "pdd" => Self::Image,
-            "mp3" | "flac" | "wav" | "ogg" | "m4a" => Self::Audio,
-            "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "txt" | "rs" | "md"
-            | "html" | "xml" | "json" | "csv" | "log" | "conf" | "ini" | "yml" | "yaml"
-            | "toml" | "sh" | "bat" | "ps1" | "py" | "js" | "ts" | "c" | "cpp" | "h" | "hpp"
-            | "cs" | "java" | "kt" | "swift" | "rb" | "pl" | "php" | "go" | "sql" | "asm"
-            | "asmx" | "aspx" | "jsp" | "cshtml" | "jsx" | "tsx" | "rsx" | "vue" | "svelte"
-            | "elm" | "ml" | "fs" | "fsx" | "fsi" | "clj" | "cljs" | "cljc" | "edn" | "ex"
-            | "pak" | "exs" | "erl" | "hrl" | "hs" | "lhs" | "purs" | "scm" | "ss" | "rkt"
-            | "jl" | "dylib" => Self::Document,
-            "svg" => Self::Svg,
-            _ => Self::Other,
+    /// The preview of a MIME type.
+    fn from(mime_type: &str) -> Self {
+        if mime_type == "image/svg+xml" {
+            return Self::Svg;
+        }
+        match ContentKind::from(mime_type) {
+            ContentKind::Image => Self::Image,
+            ContentKind::Video => Self::Video,
+            ContentKind::Audio => Self::Audio,
+            ContentKind::Document => Self::Document,
+            ContentKind::Other => Self::Other,
         }
     }
 }
@@ -47,10 +44,7 @@ pub struct Preview {
 
 impl Preview {
     pub fn new(path: PathBuf) -> Self {
-        let file_type = match path.extension().and_then(|ext| ext.to_str()) {
-            Some(ext) => PreviewFileType::from(ext.to_lowercase().as_str()),
-            None => PreviewFileType::Document,
-        };
+        let file_type = PreviewFileType::from(mime_type::detect(&path).as_str());
 
         Self { path, file_type }
     }
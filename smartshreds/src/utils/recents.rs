@@ -1,3 +1,6 @@
+use async_channel::Sender;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
     time::Duration,
@@ -7,21 +10,75 @@ use notify_debouncer_full::{
     new_debouncer,
     notify::{
         event::{CreateKind, DataChange, MetadataKind, ModifyKind, RemoveKind, RenameMode},
-        EventKind, RecursiveMode, Watcher,
+        Event, EventKind, RecursiveMode, Watcher,
     },
 };
 
-#[derive(Debug)]
+/// Something that happened to a file or folder of a watched folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Action {
-    Create(String),
-    Remove(String),
-    Modify(String),
-    Rename(String),
+    /// Created, or `moved` in from outside the watched folders.
+    Create {
+        moved: bool,
+    },
+    /// Removed, or `moved` away out of the watched folders.
+    Remove {
+        moved: bool,
+    },
+    Modify(Change),
+    Access,
+    /// Renamed in the same folder.
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Moved to another folder, renamed or not.
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+/// What kind of action was taken, without its details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ActionKind {
+    Create,
+    Remove,
+    Modify,
+    Access,
+    Rename,
     Move,
-    Unknown,
 }
 
-#[derive(Debug)]
+impl ActionKind {
+    pub const ALL: [ActionKind; 6] = [
+        ActionKind::Create,
+        ActionKind::Remove,
+        ActionKind::Modify,
+        ActionKind::Access,
+        ActionKind::Rename,
+        ActionKind::Move,
+    ];
+}
+
+/// What was modified, or for a create or remove, whether the entry was moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Change {
+    /// Moved in from or away out of the watched folders.
+    Moved,
+    Content,
+    Size,
+    Permissions,
+    Ownership,
+    WriteTime,
+    AccessTime,
+    Extended,
+    /// Renamed, the other name not being known.
+    Name,
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Node {
     File,
     Folder,
@@ -40,137 +97,363 @@ impl From<&PathBuf> for Node {
     }
 }
 
-impl Into<String> for Node {
-    fn into(self) -> String {
-        match self {
-            Node::File => "file".to_string(),
-            Node::Folder => "folder".to_string(),
-            Node::Unknown => "".to_string(),
+impl Node {
+    /// What an event is about, told by the event itself when the entry is gone.
+    pub fn of(event_kind: EventKind, path: &PathBuf) -> Self {
+        match (Node::from(path), event_kind) {
+            (Node::Unknown, EventKind::Create(CreateKind::File))
+            | (Node::Unknown, EventKind::Remove(RemoveKind::File)) => Node::File,
+            (Node::Unknown, EventKind::Create(CreateKind::Folder))
+            | (Node::Unknown, EventKind::Remove(RemoveKind::Folder)) => Node::Folder,
+            (node, _) => node,
         }
     }
 }
 
 impl Action {
-    /// The string representation of the action is displayed on the Recents panel.
-    pub fn from_event(event_kind: EventKind, node: Node, entry: String) -> Self {
-        let node: String = node.into();
-
-        match event_kind {
-            EventKind::Create(kind) => match kind {
-                CreateKind::File => {
-                    Action::Create(format!("The {} {} has been created", node, entry))
-                }
-                CreateKind::Folder => {
-                    Action::Create(format!("The {} {} has been created", node, entry))
-                }
-                _ => Action::Create(format!("{}{} has been created", node, entry)),
-            },
-            EventKind::Remove(kind) => match kind {
-                RemoveKind::File => {
-                    Action::Remove(format!("The {} {} has been removed", node, entry))
-                }
-                RemoveKind::Folder => {
-                    Action::Remove(format!("The {} {} has been removed", node, entry))
-                }
-                _ => Action::Remove(format!("{}{} has been removed", node, entry)),
-            },
+    /// The action of a watcher event, `None` for events the Recents panel ignores.
+    pub fn from_event(event_kind: EventKind) -> Option<Self> {
+        let action = match event_kind {
+            EventKind::Create(_) => Action::Create { moved: false },
+            EventKind::Remove(_) => Action::Remove { moved: false },
             EventKind::Modify(kind) => match kind {
                 ModifyKind::Data(data_changed) => match data_changed {
-                    DataChange::Content => Action::Modify(format!(
-                        "The content of the {} {} has been modified",
-                        node, entry
-                    )),
-                    DataChange::Size => Action::Modify(format!(
-                        "The size of the {} {} has been modified",
-                        node, entry
-                    )),
-                    _ => Action::Modify(format!("The {} {} has been modified", node, entry)),
+                    DataChange::Content => Action::Modify(Change::Content),
+                    DataChange::Size => Action::Modify(Change::Size),
+                    _ => Action::Modify(Change::Any),
                 },
+                // a half whose other half was not seen was moved out of or into the
+                // watched folders, the paired halves are turned into `Rename` or `Move`
                 ModifyKind::Name(rename_mode) => match rename_mode {
-                    RenameMode::From => {
-                        Action::Rename(format!("The {} {} has been renamed", node, entry))
-                    }
-                    RenameMode::To => {
-                        Action::Rename(format!("The {} {} has been renamed", node, entry))
-                    }
-                    _ => Action::Rename(format!("The {} {} has been renamed", node, entry)),
+                    RenameMode::From => Action::Remove { moved: true },
+                    RenameMode::To => Action::Create { moved: true },
+                    _ => Action::Modify(Change::Name),
                 },
                 ModifyKind::Metadata(metadata_kind) => match metadata_kind {
-                    MetadataKind::Permissions => Action::Modify(format!(
-                        "The permissions of the {} {} have been modified",
-                        node, entry
-                    )),
-                    MetadataKind::Ownership => Action::Modify(format!(
-                        "The ownership of the {} {} has been modified",
-                        node, entry
-                    )),
-                    MetadataKind::WriteTime => Action::Modify(format!(
-                        "The write time of the {} {} has been modified",
-                        node, entry
-                    )),
-                    MetadataKind::AccessTime => Action::Modify(format!(
-                        "The access time of the {} {} has been modified",
-                        node, entry
-                    )),
-                    MetadataKind::Extended => Action::Modify(format!(
-                        "The extended metadata of the {} {} has been modified",
-                        node, entry
-                    )),
-                    _ => Action::Modify(format!("The {} {} has been modified", node, entry)),
+                    MetadataKind::Permissions => Action::Modify(Change::Permissions),
+                    MetadataKind::Ownership => Action::Modify(Change::Ownership),
+                    MetadataKind::WriteTime => Action::Modify(Change::WriteTime),
+                    MetadataKind::AccessTime => Action::Modify(Change::AccessTime),
+                    MetadataKind::Extended => Action::Modify(Change::Extended),
+                    _ => Action::Modify(Change::Any),
                 },
-                _ => Action::Modify(format!("The {} {} has been modified", node, entry)),
+                _ => Action::Modify(Change::Any),
             },
-            EventKind::Access(_) => {
-                Action::Modify(format!("The {} {} has been accessed", node, entry))
-            }
-            _ => Action::Unknown,
+            EventKind::Access(_) => Action::Access,
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    /// `Rename` if `from` and `to` are in the same folder, `Move` otherwise.
+    pub fn moved(from: PathBuf, to: PathBuf) -> Self {
+        if from.parent() == to.parent() {
+            Action::Rename { from, to }
+        } else {
+            Action::Move { from, to }
+        }
+    }
+
+    /// The action back from its `kind`, `change` and `from`, for an entry now at `path`.
+    pub fn of_parts(
+        kind: ActionKind,
+        change: Option<Change>,
+        from: Option<PathBuf>,
+        path: &Path,
+    ) -> Self {
+        let moved = change == Some(Change::Moved);
+        match (kind, from) {
+            (ActionKind::Create, _) => Action::Create { moved },
+            (ActionKind::Remove, _) => Action::Remove { moved },
+            (ActionKind::Access, _) => Action::Access,
+            (ActionKind::Rename, Some(from)) => Action::Rename {
+                from,
+                to: path.to_path_buf(),
+            },
+            (ActionKind::Move, Some(from)) => Action::Move {
+                from,
+                to: path.to_path_buf(),
+            },
+            (ActionKind::Rename | ActionKind::Move, None) => Action::Modify(Change::Name),
+            (ActionKind::Modify, _) => Action::Modify(change.unwrap_or(Change::Any)),
+        }
+    }
+
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::Create { .. } => ActionKind::Create,
+            Action::Remove { .. } => ActionKind::Remove,
+            Action::Modify(_) => ActionKind::Modify,
+            Action::Access => ActionKind::Access,
+            Action::Rename { .. } => ActionKind::Rename,
+            Action::Move { .. } => ActionKind::Move,
+        }
+    }
+
+    /// What was modified, or `Moved` for an entry moved in or away.
+    pub fn change(&self) -> Option<Change> {
+        match self {
+            Action::Create { moved: true } | Action::Remove { moved: true } => Some(Change::Moved),
+            Action::Modify(change) => Some(*change),
+            _ => None,
+        }
+    }
+
+    /// Where a renamed or moved entry was before.
+    pub fn from(&self) -> Option<&Path> {
+        match self {
+            Action::Rename { from, .. } | Action::Move { from, .. } => Some(from),
+            _ => None,
         }
     }
 }
 
-#[derive(Debug)]
+/// Which actions the Recents panel shows, `None` standing for any.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ActionFilter {
+    pub kind: Option<ActionKind>,
+    pub node: Option<Node>,
+}
+
+impl ActionFilter {
+    pub fn matches(&self, kind: ActionKind, node: Node) -> bool {
+        self.kind.is_none_or(|k| k == kind) && self.node.is_none_or(|n| n == node)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ActionEvent {
     pub action: Action,
+    pub node: Node,
     pub file_path: PathBuf,
+    pub time: DateTime<Utc>,
+}
+
+impl ActionEvent {
+    /// The action a watcher event stands for, `None` for events the Recents panel ignores.
+    pub fn from_event(event: &Event, time: DateTime<Utc>) -> Option<Self> {
+        if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to, ..]) =
+            (event.kind, event.paths.as_slice())
+        {
+            return Some(ActionEvent::moved(from, to, time));
+        }
+
+        let path = event.paths.first()?;
+        Some(ActionEvent {
+            action: Action::from_event(event.kind)?,
+            node: Node::of(event.kind, path),
+            file_path: path.to_path_buf(),
+            time,
+        })
+    }
+
+    fn moved(from: &Path, to: &Path, time: DateTime<Utc>) -> Self {
+        ActionEvent {
+            action: Action::moved(from.to_path_buf(), to.to_path_buf()),
+            node: Node::from(&to.to_path_buf()),
+            file_path: to.to_path_buf(),
+            time,
+        }
+    }
+}
+
+/// The actions of a batch of watcher events, the two halves of a rename paired
+/// into a single `Rename` or `Move`.
+pub fn action_events(events: &[Event], time: DateTime<Utc>) -> Vec<ActionEvent> {
+    let mut action_events = Vec::new();
+    // `From` halves waiting for their `To`
+    let mut pending: Vec<&Event> = Vec::new();
+
+    for event in events {
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => pending.push(event),
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                // the halves share a tracker when the backend gives one, otherwise
+                // the `To` comes right after its `From`
+                let half = match event.tracker() {
+                    Some(tracker) => pending
+                        .iter()
+                        .position(|from| from.tracker() == Some(tracker)),
+                    None => pending.len().checked_sub(1),
+                };
+                let paths =
+                    half.and_then(|i| Some((pending[i].paths.first()?, event.paths.first()?, i)));
+                match paths {
+                    Some((from, to, i)) => {
+                        action_events.push(ActionEvent::moved(from, to, time));
+                        pending.remove(i);
+                    }
+                    None => action_events.extend(ActionEvent::from_event(event, time)),
+                }
+            }
+            _ => action_events.extend(ActionEvent::from_event(event, time)),
+        }
+    }
+
+    // these were moved out of the watched folders
+    action_events.extend(
+        pending
+            .into_iter()
+            .filter_map(|event| ActionEvent::from_event(event, time)),
+    );
+    action_events
 }
 
-pub fn watch(path: &Path) {
+/// Watch every folder of `roots` with a single debouncer and send what happens in
+/// them to `sender`. Blocks until the receiving end is closed.
+pub fn watch(roots: &[PathBuf], sender: Sender<ActionEvent>) {
     let (tx, rx) = std::sync::mpsc::channel();
-    let mut debouncer =
-        new_debouncer(Duration::from_secs(2), None, tx).expect("Failed to create debouncer");
+    let mut debouncer = match new_debouncer(Duration::from_secs(2), None, tx) {
+        Ok(debouncer) => debouncer,
+        Err(e) => {
+            eprintln!("Error creating the file watcher: {}", e);
+            return;
+        }
+    };
 
-    debouncer.watcher().watch(path, RecursiveMode::Recursive).expect("Failed to watch path");
+    for root in roots {
+        if let Err(e) = debouncer.watcher().watch(root, RecursiveMode::Recursive) {
+            eprintln!("Error watching {:?}: {}", root, e);
+            continue;
+        }
+        // file ids let the debouncer tell the two halves of a rename apart
+        debouncer.cache().add_root(root, RecursiveMode::Recursive);
+    }
 
     for res in rx {
         match res {
             Ok(events) => {
-                events.iter().for_each(|event| {
-                    println!("Original event: {:?}", event);
-                    let event_kind = event.kind;
-                    let path = event.paths.first().expect("No path found");
-                    let entry = path
-                        .file_name()
-                        .expect("No file/folder name found")
-                        .to_str()
-                        .unwrap()
-                        .to_string();
-                    let node = Node::from(path);
-                    let action = Action::from_event(event_kind, node, entry);
-
-                    let action_event = ActionEvent {
-                        action,
-                        file_path: path.to_path_buf(),
-                    };
-
-                    println!("{:?}", action_event);
-                });
+                let events: Vec<Event> = events.into_iter().map(|event| event.event).collect();
+                for action_event in action_events(&events, Utc::now()) {
+                    if sender.send_blocking(action_event).is_err() {
+                        return;
+                    }
+                }
             }
-            Err(error) => println!("Error: {error:?}"),
+            Err(errors) => errors
+                .iter()
+                .for_each(|e| eprintln!("Error watching files: {}", e)),
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    
+    use super::*;
+    use notify_debouncer_full::notify::event::AccessKind;
+
+    #[test]
+    fn test_action_event() {
+        let now = Utc::now();
+        let path = PathBuf::from("/tmp/smartshreds-missing/report.pdf");
+        let event = Event::new(EventKind::Create(CreateKind::File)).add_path(path.clone());
+        let action_event = ActionEvent::from_event(&event, now).unwrap();
+        assert_eq!(action_event.file_path, path);
+        assert_eq!(action_event.time, now);
+        assert_eq!(action_event.action, Action::Create { moved: false });
+        // the file is gone, the event tells what it was
+        assert_eq!(action_event.node, Node::File);
+
+        let event = Event::new(EventKind::Modify(ModifyKind::Metadata(
+            MetadataKind::Permissions,
+        )))
+        .add_path(path.clone());
+        let action = ActionEvent::from_event(&event, now).unwrap().action;
+        assert_eq!(action.kind(), ActionKind::Modify);
+        assert_eq!(action.change(), Some(Change::Permissions));
+
+        let event = Event::new(EventKind::Access(AccessKind::Any)).add_path(path);
+        assert_eq!(
+            ActionEvent::from_event(&event, now).unwrap().action,
+            Action::Access
+        );
+        assert!(ActionEvent::from_event(&Event::new(EventKind::Other), now).is_none());
+        let event = Event::new(EventKind::Any).add_path(PathBuf::from("/"));
+        assert!(ActionEvent::from_event(&event, now).is_none());
+    }
+
+    #[test]
+    fn test_rename_pairing() {
+        let now = Utc::now();
+        let name = |mode| EventKind::Modify(ModifyKind::Name(mode));
+        let path = PathBuf::from;
+        let kinds = |events: &[Event]| -> Vec<ActionKind> {
+            action_events(events, now)
+                .iter()
+                .map(|action_event| action_event.action.kind())
+                .collect()
+        };
+
+        let both = Event::new(name(RenameMode::Both))
+            .add_path(path("/docs/a.txt"))
+            .add_path(path("/docs/b.txt"));
+        let renamed = action_events(&[both], now);
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0].file_path, path("/docs/b.txt"));
+        let Action::Rename { from, to } = &renamed[0].action else {
+            panic!("Not a rename: {:?}", renamed[0].action);
+        };
+        assert_eq!((from, to), (&path("/docs/a.txt"), &path("/docs/b.txt")));
+        let action = &renamed[0].action;
+        let rebuilt = Action::of_parts(
+            action.kind(),
+            action.change(),
+            action.from().map(Path::to_path_buf),
+            &renamed[0].file_path,
+        );
+        assert_eq!(&rebuilt, action);
+
+        // halves paired by their tracker, around an unrelated event
+        let events = [
+            Event::new(name(RenameMode::From))
+                .add_path(path("/docs/a.txt"))
+                .set_tracker(7),
+            Event::new(EventKind::Create(CreateKind::File)).add_path(path("/docs/c.txt")),
+            Event::new(name(RenameMode::To))
+                .add_path(path("/music/a.txt"))
+                .set_tracker(7),
+        ];
+        assert_eq!(kinds(&events), vec![ActionKind::Create, ActionKind::Move]);
+
+        // without trackers the `To` follows its `From`
+        let events = [
+            Event::new(name(RenameMode::From)).add_path(path("/docs/a.txt")),
+            Event::new(name(RenameMode::To)).add_path(path("/docs/b.txt")),
+        ];
+        assert_eq!(kinds(&events), vec![ActionKind::Rename]);
+
+        // halves from or to outside the watched folders
+        let events = [
+            Event::new(name(RenameMode::To))
+                .add_path(path("/docs/in.txt"))
+                .set_tracker(1),
+            Event::new(name(RenameMode::From))
+                .add_path(path("/docs/out.txt"))
+                .set_tracker(2),
+        ];
+        let actions: Vec<Action> = action_events(&events, now)
+            .into_iter()
+            .map(|action_event| action_event.action)
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                Action::Create { moved: true },
+                Action::Remove { moved: true }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_action_filter() {
+        let filter = ActionFilter::default();
+        assert!(filter.matches(ActionKind::Remove, Node::Unknown));
+        let filter = ActionFilter {
+            kind: Some(ActionKind::Modify),
+            node: Some(Node::Folder),
+        };
+        assert!(filter.matches(ActionKind::Modify, Node::Folder));
+        assert!(!filter.matches(ActionKind::Modify, Node::File));
+        assert!(!filter.matches(ActionKind::Create, Node::Folder));
+    }
 }
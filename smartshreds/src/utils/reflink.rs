@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// `_IOW(0x94, 9, int)`, clones a whole file into another one.
+const FICLONE: u64 = 0x4004_9409;
+/// `_IOWR(0x94, 54, struct file_dedupe_range)`, shares identical ranges of files.
+const FIDEDUPERANGE: u64 = 0xc018_9436;
+/// The kernel status of a range whose content differs.
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+/// Filesystems cap how much is deduplicated per call, Btrfs at 16 MiB.
+const DEDUPE_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Whether the filesystem of a device supports reflinks, probed once per device.
+static REFLINK_SUPPORT: Mutex<Option<HashMap<u64, bool>>> = Mutex::new(None);
+
+/// `struct file_dedupe_range` with room for a single destination.
+#[repr(C)]
+struct FileDedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+    info: FileDedupeRangeInfo,
+}
+
+/// `struct file_dedupe_range_info`
+#[repr(C)]
+struct FileDedupeRangeInfo {
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+/// Whether files in `dir` can share their data blocks, as on Btrfs or XFS.
+///
+/// The first call for a filesystem clones a small file in `dir` to find out,
+/// later calls for the same filesystem return the cached answer.
+pub fn is_supported(dir: &Path) -> bool {
+    let Ok(metadata) = fs::metadata(dir) else {
+        return false;
+    };
+    let mut support = REFLINK_SUPPORT.lock().unwrap_or_else(|e| e.into_inner());
+    *support
+        .get_or_insert_with(HashMap::new)
+        .entry(metadata.dev())
+        .or_insert_with(|| probe(dir).unwrap_or(false))
+}
+
+fn probe(dir: &Path) -> io::Result<bool> {
+    let source_path = dir.join(format!(".smartshreds-reflink-probe-{}", std::process::id()));
+    let clone_path = source_path.with_extension("clone");
+    let result = (|| {
+        let mut source = File::create(&source_path)?;
+        source.write_all(&[0xa5; 4096])?;
+        source.sync_all()?;
+        let clone = File::create(&clone_path)?;
+        Ok(clone_file(&source, &clone).is_ok())
+    })();
+    let _ = fs::remove_file(&source_path);
+    let _ = fs::remove_file(&clone_path);
+    result
+}
+
+/// Make `destination` share every block of `source`.
+fn clone_file(source: &File, destination: &File) -> io::Result<()> {
+    // SAFETY: both descriptors are open for the duration of the call
+    let result = unsafe { libc::ioctl(destination.as_raw_fd(), FICLONE as _, source.as_raw_fd()) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Make `copy` share the data blocks of `keeper`. Both stay separate files with
+/// their own name, inode and metadata; the kernel checks that their content is
+/// identical and refuses otherwise.
+pub fn dedupe(copy: &Path, keeper: &Path) -> io::Result<()> {
+    let source = File::open(keeper)?;
+    let destination = OpenOptions::new().write(true).open(copy)?;
+    let length = source.metadata()?.len();
+    if destination.metadata()?.len() != length {
+        return Err(differs(copy, keeper));
+    }
+
+    let mut offset = 0;
+    while offset < length {
+        let mut range = FileDedupeRange {
+            src_offset: offset,
+            src_length: (length - offset).min(DEDUPE_CHUNK_SIZE),
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            info: FileDedupeRangeInfo {
+                dest_fd: destination.as_raw_fd() as i64,
+                dest_offset: offset,
+                bytes_deduped: 0,
+                status: 0,
+                reserved: 0,
+            },
+        };
+        // SAFETY: `range` is laid out as the kernel expects and outlives the call
+        let result = unsafe { libc::ioctl(source.as_raw_fd(), FIDEDUPERANGE as _, &mut range) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        match range.info.status {
+            FILE_DEDUPE_RANGE_DIFFERS => return Err(differs(copy, keeper)),
+            status if status < 0 => return Err(io::Error::from_raw_os_error(-status)),
+            _ => {}
+        }
+        if range.info.bytes_deduped == 0 {
+            return Err(io::Error::other(format!(
+                "{} could not be deduplicated",
+                copy.display()
+            )));
+        }
+        offset += range.info.bytes_deduped;
+    }
+    Ok(())
+}
+
+fn differs(copy: &Path, keeper: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} differs from {}", copy.display(), keeper.display()),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    #[test]
+    fn test_dedupe() {
+        // reflinks need Btrfs, XFS or similar; point TMPDIR to one to run this test
+        let base = TestDir::new("dedupe");
+        if !is_supported(&base) {
+            eprintln!("Skipping test_dedupe: no reflink support in {:?}", base);
+            return;
+        }
+
+        let content: Vec<u8> = (0..3 * 65536).map(|i| (i % 251) as u8).collect();
+        fs::write(base.join("keeper.bin"), &content).unwrap();
+        fs::write(base.join("copy.bin"), &content).unwrap();
+        let mut different = content.clone();
+        different[100_000] ^= 1;
+        fs::write(base.join("different.bin"), &different).unwrap();
+
+        let inode = fs::metadata(base.join("copy.bin")).unwrap().ino();
+        dedupe(&base.join("copy.bin"), &base.join("keeper.bin")).unwrap();
+        assert_eq!(fs::metadata(base.join("copy.bin")).unwrap().ino(), inode);
+        assert_eq!(fs::read(base.join("copy.bin")).unwrap(), content);
+        assert!(dedupe(&base.join("different.bin"), &base.join("keeper.bin")).is_err());
+        assert_eq!(fs::read(base.join("different.bin")).unwrap(), different);
+    }
+}
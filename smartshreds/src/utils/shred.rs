@@ -0,0 +1,331 @@
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Size of the buffer the passes are written with.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// `f_type` of the filesystems that write changes to new blocks instead of
+/// overwriting the old ones.
+const COPY_ON_WRITE_FILESYSTEMS: &[(u32, &str)] = &[
+    (0x9123_683e, "Btrfs"),
+    (0x2fc1_2fc1, "ZFS"),
+    (0xca45_1a4e, "bcachefs"),
+    (0xf2f5_2010, "F2FS"),
+    (0x2468, "NILFS"),
+];
+
+/// How the content of a file is destroyed before it is unlinked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShredMode {
+    /// A single pass of zeros.
+    #[default]
+    Zeros,
+    /// A single pass of random bytes.
+    Random,
+    /// The three passes of DoD 5220.22-M: zeros, ones, then random bytes.
+    Dod,
+}
+
+/// One overwrite of the whole file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    Fill(u8),
+    Random,
+}
+
+impl ShredMode {
+    pub const ALL: [ShredMode; 3] = [ShredMode::Zeros, ShredMode::Random, ShredMode::Dod];
+
+    /// Parse a mode as stored in the settings, `None` if it is unknown.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "zeros" => Some(ShredMode::Zeros),
+            "random" => Some(ShredMode::Random),
+            "dod" => Some(ShredMode::Dod),
+            _ => None,
+        }
+    }
+
+    /// A short description for the user.
+    pub fn description(&self) -> &'static str {
+        match self {
+            ShredMode::Zeros => "Zeros, 1 pass",
+            ShredMode::Random => "Random data, 1 pass",
+            ShredMode::Dod => "DoD 5220.22-M, 3 passes",
+        }
+    }
+
+    fn passes(&self) -> &'static [Pass] {
+        match self {
+            ShredMode::Zeros => &[Pass::Fill(0)],
+            ShredMode::Random => &[Pass::Random],
+            ShredMode::Dod => &[Pass::Fill(0), Pass::Fill(0xff), Pass::Random],
+        }
+    }
+}
+
+impl fmt::Display for ShredMode {
+    /// The mode as stored in the settings.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShredMode::Zeros => write!(f, "zeros"),
+            ShredMode::Random => write!(f, "random"),
+            ShredMode::Dod => write!(f, "dod"),
+        }
+    }
+}
+
+/// Why overwriting a file may leave its old content readable on the disk.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ShredWarning {
+    /// The filesystem writes the passes to new blocks.
+    CopyOnWrite(&'static str),
+    /// The drive remaps writes and keeps old blocks around until it erases them.
+    SolidState,
+    /// The file has other hard links, so only its name is removed.
+    Hardlinked,
+}
+
+impl fmt::Display for ShredWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShredWarning::CopyOnWrite(filesystem) => write!(
+                f,
+                "{} is a copy-on-write filesystem: overwrites go to new blocks and snapshots may keep the old ones",
+                filesystem
+            ),
+            ShredWarning::SolidState => write!(
+                f,
+                "The drive is a solid-state drive: it may keep old blocks until it erases them"
+            ),
+            ShredWarning::Hardlinked => write!(
+                f,
+                "Some files have other hard links: their content is left in place for the other links"
+            ),
+        }
+    }
+}
+
+/// What may make shredding `path` unreliable, empty if overwrites are expected to stick.
+pub fn shred_warnings(path: &Path) -> Vec<ShredWarning> {
+    let mut warnings = Vec::new();
+    if let Some(filesystem) = copy_on_write_filesystem(path) {
+        warnings.push(ShredWarning::CopyOnWrite(filesystem));
+    }
+    if is_solid_state(path) {
+        warnings.push(ShredWarning::SolidState);
+    }
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.nlink() > 1) {
+        warnings.push(ShredWarning::Hardlinked);
+    }
+    warnings
+}
+
+fn copy_on_write_filesystem(path: &Path) -> Option<&'static str> {
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: an all-zero `statfs` is valid, and it outlives the call that fills it
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    COPY_ON_WRITE_FILESYSTEMS
+        .iter()
+        .find(|(magic, _)| *magic == stat.f_type as u32)
+        .map(|(_, name)| *name)
+}
+
+/// Whether the block device holding `path` is non-rotational, as told by sysfs.
+fn is_solid_state(path: &Path) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+    let dev = metadata.dev();
+    let major = ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0xfff);
+    let minor = ((dev >> 12) & 0xffff_ff00) | (dev & 0xff);
+    let Ok(device_dir) = fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)) else {
+        return false;
+    };
+    // partitions have no queue of their own, their disk is the parent directory
+    [device_dir.join("queue"), device_dir.join("../queue")]
+        .iter()
+        .find_map(|queue| fs::read_to_string(queue.join("rotational")).ok())
+        .is_some_and(|rotational| rotational.trim() == "0")
+}
+
+/// Overwrite a file according to `mode`, give it a meaningless name and remove it.
+///
+/// Directories are shredded with everything below them. Symlinks are removed
+/// without touching what they point to, and so are files with other hard links,
+/// whose content the other links still use.
+pub fn shred(path: &Path, mode: ShredMode) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            shred(&entry?.path(), mode)?;
+        }
+        let path = obfuscate_name(path)?;
+        fs::remove_dir(&path)?;
+    } else {
+        if metadata.is_file() && metadata.nlink() == 1 {
+            overwrite(path, mode.passes())?;
+        }
+        let path = obfuscate_name(path)?;
+        fs::remove_file(&path)?;
+    }
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_dir(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Overwrite the whole content of a file with every pass, then truncate it.
+fn overwrite(path: &Path, passes: &[Pass]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    write_passes(&mut file, passes)?;
+    file.set_len(0)?;
+    file.sync_all()
+}
+
+/// Write every pass over the content of a file, flushing it to the disk after each of them.
+fn write_passes(file: &mut File, passes: &[Pass]) -> io::Result<()> {
+    let length = file.metadata()?.len();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut random = RandomBytes::new();
+
+    for pass in passes {
+        file.rewind()?;
+        if let Pass::Fill(byte) = pass {
+            buffer.fill(*byte);
+        }
+        let mut remaining = length;
+        while remaining > 0 {
+            let chunk = remaining.min(BUFFER_SIZE as u64) as usize;
+            if *pass == Pass::Random {
+                random.fill(&mut buffer[..chunk]);
+            }
+            file.write_all(&buffer[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Rename a file to shorter and shorter names of zeros, so its directory keeps
+/// no trace of its name, and return its final path.
+fn obfuscate_name(path: &Path) -> io::Result<PathBuf> {
+    let Some(parent) = path.parent() else {
+        return Ok(path.to_path_buf());
+    };
+    let length = path.file_name().map_or(1, |name| name.len());
+    let mut current = path.to_path_buf();
+    for n in (1..=length).rev() {
+        let candidate = parent.join("0".repeat(n));
+        if fs::symlink_metadata(&candidate).is_ok() {
+            continue;
+        }
+        fs::rename(&current, &candidate)?;
+        sync_dir(parent)?;
+        current = candidate;
+    }
+    Ok(current)
+}
+
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// xorshift64*, seeded from the kernel. It only has to be unpredictable enough
+/// that the pass cannot be told apart from noise, and fast enough for large files.
+struct RandomBytes(u64);
+
+impl RandomBytes {
+    fn new() -> Self {
+        let mut seed = [0; 8];
+        let seeded =
+            File::open("/dev/urandom").and_then(|mut urandom| urandom.read_exact(&mut seed));
+        let seed = match seeded {
+            Ok(()) => u64::from_ne_bytes(seed),
+            Err(_) => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos() as u64),
+        };
+        RandomBytes(seed | 1)
+    }
+
+    fn fill(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(8) {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            let bytes = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d).to_ne_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    #[test]
+    fn test_overwrite() {
+        let base = TestDir::new("overwrite");
+        let path = base.join("secret.txt");
+        let length = 100_000;
+        fs::write(&path, "my secret ".repeat(length / 10)).unwrap();
+
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        write_passes(&mut file, &[Pass::Fill(0xff)]).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![0xff; length]);
+        write_passes(&mut file, &[Pass::Fill(0), Pass::Random]).unwrap();
+        let content = fs::read(&path).unwrap();
+        assert_eq!(content.len(), length);
+        assert!(content.iter().filter(|&&byte| byte == 0).count() < length / 100);
+
+        overwrite(&path, ShredMode::Zeros.passes()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_shred() {
+        let base = TestDir::new("shred");
+        fs::create_dir_all(base.join("dir/album")).unwrap();
+        fs::write(base.join("dir/secret.txt"), "secret").unwrap();
+        fs::write(base.join("dir/album/photo.jpg"), "photo").unwrap();
+        fs::write(base.join("0"), "a file named like the shredded ones").unwrap();
+
+        shred(&base.join("dir"), ShredMode::Dod).unwrap();
+
+        let left: Vec<PathBuf> = fs::read_dir(&base)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(left, vec![base.join("0")]);
+        for mode in ShredMode::ALL {
+            assert_eq!(ShredMode::parse(&mode.to_string()), Some(mode));
+        }
+    }
+
+    #[test]
+    fn test_shred_hardlink() {
+        let base = TestDir::new("shred_hardlink");
+        let original = base.join("original.txt");
+        let link = base.join("link.txt");
+        fs::write(&original, "shared content").unwrap();
+        fs::hard_link(&original, &link).unwrap();
+        assert!(shred_warnings(&link).contains(&ShredWarning::Hardlinked));
+
+        shred(&link, ShredMode::Dod).unwrap();
+
+        assert!(!link.exists());
+        assert_eq!(fs::read_to_string(&original).unwrap(), "shared content");
+        assert!(!shred_warnings(&original).contains(&ShredWarning::Hardlinked));
+    }
+}
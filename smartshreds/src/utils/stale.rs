@@ -0,0 +1,290 @@
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use super::disks::mount_point;
+
+/// Days without use after which files are reported, one bucket per age.
+pub const DEFAULT_STALE_DAYS: [u32; 4] = [30, 90, 180, 365];
+
+/// How a filesystem updates the access time of its files, from its mount options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtimePolicy {
+    /// Every read updates it.
+    Strict,
+    /// `relatime`, the Linux default: updated when older than the modification
+    /// time or than a day, which is precise enough to tell stale files.
+    Relative,
+    /// `noatime`: never updated, only the modification time tells when a file was used.
+    Never,
+}
+
+impl AtimePolicy {
+    /// The policy of a comma separated list of mount options.
+    pub fn of_options(options: &str) -> Self {
+        let options: Vec<&str> = options.split(',').collect();
+        if options.contains(&"noatime") {
+            AtimePolicy::Never
+        } else if options.contains(&"strictatime") {
+            AtimePolicy::Strict
+        } else {
+            AtimePolicy::Relative
+        }
+    }
+
+    /// The policy of the filesystem holding `path`, `Relative` if its mount is not found.
+    pub fn of(path: &Path) -> Self {
+        let Ok(mount_point) = mount_point(path) else {
+            return AtimePolicy::Relative;
+        };
+        let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+            return AtimePolicy::Relative;
+        };
+        // a later mount on the same directory hides the earlier ones
+        mounts
+            .lines()
+            .rev()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let target = unescape_mount_path(fields.nth(1)?);
+                let options = fields.nth(1)?;
+                Some((target, options))
+            })
+            .find(|(target, _)| Path::new(target) == mount_point)
+            .map_or(AtimePolicy::Relative, |(_, options)| {
+                AtimePolicy::of_options(options)
+            })
+    }
+
+    /// When a file was last used, and whether that is only its modification time.
+    pub fn last_used(&self, metadata: &Metadata) -> (DateTime<Utc>, bool) {
+        let modified = metadata
+            .modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_default();
+        let accessed = metadata
+            .accessed()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_default();
+        match self {
+            AtimePolicy::Never => (modified, true),
+            _ if accessed > modified => (accessed, false),
+            _ => (modified, false),
+        }
+    }
+}
+
+/// `/proc/self/mounts` writes spaces, tabs, newlines and backslashes as octal escapes.
+fn unescape_mount_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                unescaped.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).to_string()
+}
+
+/// A file nobody used for a while.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_used: DateTime<Utc>,
+    /// Whether `last_used` is the modification time, the filesystem not
+    /// recording accesses.
+    pub modified_only: bool,
+}
+
+/// The stale files unused for `min_days` to `max_days`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleBucket {
+    pub min_days: u32,
+    /// `None` for the oldest bucket.
+    pub max_days: Option<u32>,
+    pub files: Vec<StaleFile>,
+}
+
+impl StaleBucket {
+    pub fn label(&self) -> String {
+        match self.max_days {
+            Some(max_days) => format!("{} to {} days", self.min_days, max_days),
+            None => format!("Over {} days", self.min_days),
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// The `count` largest files of the bucket.
+    pub fn largest(&self, count: usize) -> Vec<StaleFile> {
+        let mut files = self.files.clone();
+        files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        files.truncate(count);
+        files
+    }
+}
+
+/// Files grouped by how long they have not been used.
+#[derive(Debug, Clone)]
+pub struct StaleReport {
+    /// Youngest first.
+    buckets: Vec<StaleBucket>,
+    /// The access time policy of each device, looked up once.
+    atime_policies: HashMap<u64, AtimePolicy>,
+}
+
+impl Default for StaleReport {
+    fn default() -> Self {
+        StaleReport::new(&DEFAULT_STALE_DAYS)
+    }
+}
+
+impl StaleReport {
+    /// A report with a bucket starting at each of `days`.
+    pub fn new(days: &[u32]) -> Self {
+        let mut days: Vec<u32> = days.iter().copied().filter(|days| *days > 0).collect();
+        days.sort_unstable();
+        days.dedup();
+        let buckets = days
+            .iter()
+            .enumerate()
+            .map(|(i, min_days)| StaleBucket {
+                min_days: *min_days,
+                max_days: days.get(i + 1).copied(),
+                files: Vec::new(),
+            })
+            .collect();
+
+        StaleReport {
+            buckets,
+            atime_policies: HashMap::new(),
+        }
+    }
+
+    pub fn buckets(&self) -> &[StaleBucket] {
+        &self.buckets
+    }
+
+    /// Add a file to the bucket of its age, if it is old enough for any.
+    pub fn add(&mut self, path: &Path, metadata: &Metadata, now: DateTime<Utc>) {
+        let policy = *self
+            .atime_policies
+            .entry(metadata.dev())
+            .or_insert_with(|| AtimePolicy::of(path));
+        let (last_used, modified_only) = policy.last_used(metadata);
+        self.insert(
+            StaleFile {
+                path: path.to_path_buf(),
+                size: metadata.len(),
+                last_used,
+                modified_only,
+            },
+            now,
+        );
+    }
+
+    fn insert(&mut self, file: StaleFile, now: DateTime<Utc>) {
+        let age = (now - file.last_used).num_days();
+        if let Some(bucket) = self
+            .buckets
+            .iter_mut()
+            .rev()
+            .find(|bucket| age >= bucket.min_days as i64)
+        {
+            bucket.files.push(file);
+        }
+    }
+
+    /// Forget files that were removed or moved away.
+    pub fn remove(&mut self, paths: &[PathBuf]) {
+        let paths: HashSet<&PathBuf> = paths.iter().collect();
+        for bucket in &mut self.buckets {
+            bucket.files.retain(|file| !paths.contains(&file.path));
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.files.len()).sum()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.buckets.iter().map(StaleBucket::size).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_atime_policy() {
+        assert_eq!(
+            AtimePolicy::of_options("rw,noatime,space_cache"),
+            AtimePolicy::Never
+        );
+        assert_eq!(
+            AtimePolicy::of_options("rw,relatime"),
+            AtimePolicy::Relative
+        );
+        assert_eq!(
+            AtimePolicy::of_options("rw,strictatime"),
+            AtimePolicy::Strict
+        );
+        assert_eq!(AtimePolicy::of_options("rw"), AtimePolicy::Relative);
+        assert_eq!(
+            unescape_mount_path("/media/My\\040Disk\\134x"),
+            "/media/My Disk\\x"
+        );
+
+        let metadata = fs::metadata(std::env::temp_dir()).unwrap();
+        let modified = DateTime::<Utc>::from(metadata.modified().unwrap());
+        assert_eq!(AtimePolicy::Never.last_used(&metadata), (modified, true));
+        assert!(AtimePolicy::Strict.last_used(&metadata).0 >= modified);
+    }
+
+    #[test]
+    fn test_stale_report() {
+        let now = Utc::now();
+        let file = |name: &str, days: i64, size: u64| StaleFile {
+            path: PathBuf::from(name),
+            size,
+            last_used: now - chrono::Duration::days(days),
+            modified_only: false,
+        };
+        let mut report = StaleReport::new(&[365, 30, 90, 0, 30]);
+        report.insert(file("fresh", 10, 1), now);
+        report.insert(file("month", 45, 2), now);
+        report.insert(file("quarter", 90, 4), now);
+        report.insert(file("years", 1000, 8), now);
+        report.insert(file("old", 400, 16), now);
+
+        let buckets = report.buckets();
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0].label(), "30 to 90 days");
+        assert_eq!(buckets[2].label(), "Over 365 days");
+        assert_eq!(buckets[0].size(), 2);
+        assert_eq!(buckets[1].size(), 4);
+        assert_eq!(buckets[2].largest(1)[0].path, PathBuf::from("old"));
+        assert_eq!((report.count(), report.size()), (4, 30));
+
+        report.remove(&[PathBuf::from("years")]);
+        assert_eq!((report.count(), report.size()), (3, 22));
+    }
+}
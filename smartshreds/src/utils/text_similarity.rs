@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::duplicates::{connected_groups, DupFile, SimilarFile};
+use super::mime_type::{self, ContentKind};
+
+/// Number of words in a shingle.
+const SHINGLE_WORDS: usize = 3;
+/// Number of hash functions of a MinHash signature.
+const SIGNATURE_LEN: usize = 64;
+/// Signatures are split in bands of this many hashes to find candidate pairs:
+/// two documents are only compared if one of their bands is identical.
+const BAND_ROWS: usize = 4;
+/// Larger files are left out, they are rarely hand-edited copies of each other.
+const MAX_DOCUMENT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// How near-duplicate documents are found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocumentSimilarity {
+    /// Smallest Jaccard similarity of the shingles of two documents for them to be similar.
+    pub min_similarity: f64,
+}
+
+impl Default for DocumentSimilarity {
+    fn default() -> Self {
+        DocumentSimilarity {
+            min_similarity: 0.8,
+        }
+    }
+}
+
+/// What is compared of a document: the hash of its normalized text, equal for
+/// documents that only differ by whitespace or line endings, and the MinHash
+/// signature of its shingles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentSignature {
+    pub content_hash: u64,
+    pub minhash: Vec<u64>,
+}
+
+/// Whether a file is a document worth comparing. Its content tells first, so
+/// text files without an extension are compared too; documents that turn out
+/// to be binary are left out once read by `document_signature`.
+pub fn is_text_document(file: &DupFile) -> bool {
+    file.file_size > 0
+        && file.file_size <= MAX_DOCUMENT_SIZE
+        && ContentKind::from(mime_type::detect(&file.file_path).as_str()) == ContentKind::Document
+}
+
+/// Read a document and compute its signature, `None` if it turns out to be binary.
+pub fn document_signature(path: &Path) -> io::Result<Option<DocumentSignature>> {
+    let contents = fs::read(path)?;
+    if contents.contains(&0) {
+        return Ok(None);
+    }
+    Ok(Some(signature_of(&String::from_utf8_lossy(&contents))))
+}
+
+/// Signature of a text: its words are compared, whatever whitespace separates them.
+pub fn signature_of(text: &str) -> DocumentSignature {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let content_hash = hash_words(&words);
+    let shingles: Vec<u64> = if words.len() <= SHINGLE_WORDS {
+        vec![content_hash]
+    } else {
+        words.windows(SHINGLE_WORDS).map(hash_words).collect()
+    };
+
+    let minhash = (0..SIGNATURE_LEN as u64)
+        .map(|seed| {
+            shingles
+                .iter()
+                .map(|&shingle| mix(shingle ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15)))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect();
+
+    DocumentSignature {
+        content_hash,
+        minhash,
+    }
+}
+
+/// FNV-1a of words joined by single spaces, stable across runs so signatures
+/// can be cached.
+fn hash_words(words: &[&str]) -> u64 {
+    words
+        .iter()
+        .flat_map(|word| word.bytes().chain(std::iter::once(b' ')))
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// The splitmix64 finalizer, used as the family of MinHash hash functions.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Jaccard similarity of two documents estimated from their signatures, from 0 to 1.
+pub fn similarity(a: &DocumentSignature, b: &DocumentSignature) -> f64 {
+    if a.content_hash == b.content_hash {
+        return 1.0;
+    }
+    let equal = a
+        .minhash
+        .iter()
+        .zip(&b.minhash)
+        .filter(|(a, b)| a == b)
+        .count();
+    equal as f64 / SIGNATURE_LEN as f64
+}
+
+/// Cluster documents at least `min_similarity` alike.
+///
+/// Every cluster is ordered from its largest document, used as the reference the
+/// similarity of the others is measured against.
+pub fn group_similar_documents(
+    documents: Vec<(DupFile, DocumentSignature)>,
+    min_similarity: f64,
+) -> Vec<Vec<SimilarFile>> {
+    // only documents sharing a whole band of their signature are compared
+    let mut buckets: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+    for (i, (_, signature)) in documents.iter().enumerate() {
+        for (band, rows) in signature.minhash.chunks(BAND_ROWS).enumerate() {
+            buckets.entry((band, rows)).or_default().push(i);
+        }
+    }
+    let mut pairs = Vec::new();
+    for bucket in buckets.values() {
+        for (n, &i) in bucket.iter().enumerate() {
+            for &j in &bucket[n + 1..] {
+                if similarity(&documents[i].1, &documents[j].1) >= min_similarity {
+                    pairs.push((i, j));
+                }
+            }
+        }
+    }
+    let clusters = connected_groups(documents.len(), pairs);
+
+    let mut documents: Vec<Option<(DupFile, DocumentSignature)>> =
+        documents.into_iter().map(Some).collect();
+    clusters
+        .into_iter()
+        .map(|cluster| {
+            let mut cluster: Vec<(DupFile, DocumentSignature)> = cluster
+                .into_iter()
+                .filter_map(|i| documents[i].take())
+                .collect();
+            cluster.sort_by(|a, b| {
+                b.0.file_size
+                    .cmp(&a.0.file_size)
+                    .then_with(|| a.0.file_path.cmp(&b.0.file_path))
+            });
+            let reference = cluster[0].1.clone();
+            cluster
+                .into_iter()
+                .map(|(file, signature)| SimilarFile {
+                    similarity: similarity(&reference, &signature),
+                    file,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test_dir::TestDir;
+
+    fn report(extra: &str) -> String {
+        let mut text: String = (0..200)
+            .map(|i| format!("line {} of the quarterly report\n", i))
+            .collect();
+        text.push_str(extra);
+        text
+    }
+
+    #[test]
+    fn test_is_text_document() {
+        let base = TestDir::new("is_text_document");
+        let file = |name: &str, content: &[u8]| {
+            let path = base.join(name);
+            fs::write(&path, content).unwrap();
+            DupFile::fake(path, content.len() as u64)
+        };
+
+        assert!(is_text_document(&file("README", b"plain notes\n")));
+        assert!(is_text_document(&file("report.md", b"# Report\n")));
+        assert!(!is_text_document(&file("photo", b"\x89PNG\r\n\x1a\n\0\0")));
+        assert!(!is_text_document(&file("empty.txt", b"")));
+    }
+
+    #[test]
+    fn test_whitespace_and_line_endings_are_ignored() {
+        let unix = signature_of("fn main() {\n    println!(\"hi\");\n}\n");
+        let windows = signature_of("fn main() {\r\n\tprintln!(\"hi\");\r\n}");
+
+        assert_eq!(unix.content_hash, windows.content_hash);
+        assert_eq!(similarity(&unix, &windows), 1.0);
+    }
+
+    #[test]
+    fn test_group_similar_documents() {
+        let groups = group_similar_documents(
+            vec![
+                (
+                    DupFile::fake("report_final.txt", 10),
+                    signature_of(&report("")),
+                ),
+                (
+                    DupFile::fake("report_final2.txt", 20),
+                    signature_of(&report("one more line at the end")),
+                ),
+                (
+                    DupFile::fake("notes.txt", 20),
+                    signature_of("completely different notes about something else"),
+                ),
+            ],
+            0.8,
+        );
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0][0].file.file_name, "report_final2.txt");
+        assert_eq!(groups[0][0].similarity, 1.0);
+        assert!(groups[0][1].similarity >= 0.8);
+    }
+}
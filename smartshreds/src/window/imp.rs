@@ -4,15 +4,20 @@ use gtk::{
     self,
     gio::{self, Settings},
     glib::{self, clone, subclass::InitializingObject},
-    Box, Button, CompositeTemplate, Label, ListBoxRow, ListView, Stack, Viewport,
+    Box, Button, CheckButton, CompositeTemplate, Label, ListBox, ListBoxRow, ListView, Stack,
+    ToggleButton, Viewport,
 };
 use std::{
-    cell::{OnceCell, RefCell},
+    cell::{Cell, OnceCell, RefCell},
     collections::HashMap,
+    sync::{atomic::AtomicBool, Arc, Mutex},
 };
 
+use super::AnalysisUpdate;
+use crate::ui::recents_box::RecentsBox;
 use crate::utils::{
-    auth::AuthResponse, duplicates::DuplicateFilterMode, preview::Preview, runtime,
+    analysis::StorageAnalysis, auth::AuthResponse, duplicates::DuplicateFilterMode,
+    file_ops::FileOperation, history::ActivityHistory, preview::Preview, runtime,
 };
 
 #[derive(CompositeTemplate, Default)]
@@ -20,6 +25,8 @@ use crate::utils::{
 pub struct SmartShredsWindow {
     #[template_child]
     pub main_navigation_view: TemplateChild<adw::NavigationView>,
+    #[template_child]
+    pub toastoverlay: TemplateChild<adw::ToastOverlay>,
 
     // settings
     pub settings: OnceCell<Settings>,
@@ -29,13 +36,31 @@ pub struct SmartShredsWindow {
     // home page
     #[template_child]
     pub file_type_boxes: TemplateChild<Box>,
+    /// The analysis shown, kept up to date by the file watcher once it is done.
+    pub analysis: Arc<Mutex<Option<StorageAnalysis>>>,
+    /// Set while an update of the file type cards is waiting for the main loop to be idle.
+    pub file_types_queued: Cell<bool>,
+    /// Where the thread keeping the analysis up to date is told what changed.
+    pub analysis_updates: OnceCell<async_channel::Sender<AnalysisUpdate>>,
     #[template_child]
     pub recents_and_graph: TemplateChild<Box>,
+    #[template_child]
+    pub file_reports: TemplateChild<Box>,
+    /// The activity feed, beside the treemap.
+    pub recents_box: OnceCell<RecentsBox>,
+    pub activity_history: RefCell<ActivityHistory>,
+    /// Set while an update of the timeline is waiting for the main loop to be idle.
+    pub timeline_queued: Cell<bool>,
 
     // duplicates page
     #[template_child]
     pub files_scanned: TemplateChild<Label>,
     #[template_child]
+    pub scan_status: TemplateChild<Label>,
+    #[template_child]
+    pub cancel_scan_button: TemplateChild<Button>,
+    pub scan_cancelled: RefCell<Option<Arc<AtomicBool>>>,
+    #[template_child]
     pub duplicates_count: TemplateChild<Label>,
     #[template_child]
     pub duplicates_space_taken: TemplateChild<Label>,
@@ -46,8 +71,32 @@ pub struct SmartShredsWindow {
     pub duplicates_list: TemplateChild<ListView>,
     pub duplicates: RefCell<Option<gio::ListStore>>,
     #[template_child]
+    pub sort_by_similarity_button: TemplateChild<ToggleButton>,
+    #[template_child]
     pub files_marked: TemplateChild<Label>,
     #[template_child]
+    pub keep_newest_check: TemplateChild<CheckButton>,
+    #[template_child]
+    pub keep_oldest_check: TemplateChild<CheckButton>,
+    #[template_child]
+    pub keep_shortest_path_check: TemplateChild<CheckButton>,
+    #[template_child]
+    pub keep_not_in_downloads_check: TemplateChild<CheckButton>,
+    #[template_child]
+    pub keep_preferred_directory_check: TemplateChild<CheckButton>,
+    #[template_child]
+    pub keep_rules_label: TemplateChild<Label>,
+    /// Set while the keep rule checks are updated from the settings.
+    pub loading_keep_rules: Cell<bool>,
+    /// Set while an update of `files_marked` is waiting for the main loop to be idle.
+    pub files_marked_queued: Cell<bool>,
+    #[template_child]
+    pub duplicates_space_reclaimed: TemplateChild<Label>,
+    /// Space freed by replacing duplicates with links since the last scan.
+    pub space_reclaimed: Cell<u64>,
+    #[template_child]
+    pub history_list: TemplateChild<ListBox>,
+    #[template_child]
     pub preview_viewport: TemplateChild<Viewport>,
     pub preview: RefCell<Option<Preview>>,
 
@@ -130,6 +179,72 @@ impl SmartShredsWindow {
         self.obj().apply_filters();
     }
 
+    /// Start a new duplicate scan, stopping the one in progress.
+    #[template_callback]
+    fn start_scan(&self) {
+        self.obj().get_duplicates();
+    }
+
+    #[template_callback]
+    fn cancel_scan(&self) {
+        self.obj().cancel_scan();
+    }
+
+    #[template_callback]
+    fn sort_by_similarity(&self) {
+        self.obj().apply_filters();
+    }
+
+    #[template_callback]
+    fn keep_rule_toggled(&self, check: &CheckButton) {
+        self.obj().keep_rule_toggled(check);
+    }
+
+    #[template_callback]
+    fn choose_preferred_directory(&self) {
+        self.obj().choose_preferred_directory();
+    }
+
+    #[template_callback]
+    fn apply_keep_rules(&self) {
+        self.obj().apply_keep_rules();
+    }
+
+    #[template_callback]
+    fn delete_marked(&self) {
+        self.obj().run_file_operation(FileOperation::Trash);
+    }
+
+    #[template_callback]
+    fn move_marked(&self) {
+        self.obj().choose_destination(FileOperation::Move);
+    }
+
+    #[template_callback]
+    fn copy_marked(&self) {
+        self.obj().choose_destination(FileOperation::Copy);
+    }
+
+    #[template_callback]
+    fn shred_marked(&self) {
+        self.obj().shred_marked();
+    }
+
+    #[template_callback]
+    fn link_marked(&self) {
+        self.obj().link_marked();
+    }
+
+    #[template_callback]
+    fn undo_last_cleanup(&self) {
+        self.obj().undo_cleanup(None);
+    }
+
+    #[template_callback]
+    fn show_history(&self) {
+        self.obj().display_history();
+    }
+
     #[template_callback]
     fn toggle_preview(&self, button: &Button) {
         let label = button.label().expect("Button has no label").to_string();
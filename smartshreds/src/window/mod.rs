@@ -1,31 +1,68 @@
 mod imp;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use adw::prelude::*;
 use adw::subclass::prelude::*;
+use chrono::{Local, NaiveDate};
 use glib::Object;
 use gtk::gio::Settings;
 use gtk::glib::clone;
 use gtk::{
-    gio, glib, CustomFilter, FilterListModel, ListItem, MultiSelection, SignalListItemFactory,
+    gio, glib, pango, CheckButton, CustomFilter, CustomSorter, FilterListModel, ListBox,
+    ListBoxRow, ListItem, MultiSelection, SignalListItemFactory, SortListModel, TreeExpander,
+    TreeListModel, TreeListRow,
 };
 
 use crate::ui::duplicate::duplicate_object::DuplicateObject;
 use crate::ui::duplicate::duplicate_row::DuplicateRow;
 use crate::ui::file_type_box::FileTypeBox;
+use crate::ui::junk_box::JunkBox;
+use crate::ui::largest_box::LargestBox;
 use crate::ui::recents_box::RecentsBox;
-use crate::utils::duplicates::{traverse_directory_for_duplicates, DupFile, DuplicateFilterMode};
+use crate::ui::stale_box::StaleBox;
+use crate::ui::storage_analysis_plot::StorageAnalysisPlot;
+use crate::utils::duplicates::{
+    traverse_directories_for_duplicates, DirectoryGroup, DupFile, DuplicateKind, DuplicateScan,
+    ScanEvent, ScanOptions, ScanProgress, SimilarGroup,
+};
+use crate::utils::file_ops::{replace_with_link, FileOperation, LinkKind};
+use crate::utils::hash_cache::HashCache;
+use crate::utils::history::{timeline, ActivityHistory, ActivityRecord, Period};
+use crate::utils::journal::{update_journal, Journal, JournalEntry, OperationKind};
+use crate::utils::junk::JunkFile;
+use crate::utils::keep_rules::{choose_keeper, KeepRule};
+use crate::utils::largest::LargeEntry;
+use crate::utils::perceptual::{ImageSimilarity, PerceptualHash};
 use crate::utils::preview::Preview;
-use crate::utils::recents::watch;
+use crate::utils::recents::{watch, Action, ActionEvent, ActionFilter, ActionKind, Change, Node};
+use crate::utils::shred::{shred_warnings, ShredMode};
+use crate::utils::stale::{StaleFile, StaleReport};
+use crate::utils::text_similarity::DocumentSimilarity;
 use crate::utils::{
-    analysis::StorageAnalysis,
+    analysis::{AnalysisDelta, FileType, JunkFiles, StorageAnalysis},
     auth::{AuthResponse, AuthSettings},
 };
 use crate::utils::{format_number, format_size};
 
 const APP_ID: &str = "org.gtk_rs.SmartShreds";
+/// How many of the largest files of each age are listed in the stale files panel.
+const STALE_FILES_LISTED: usize = 100;
+/// How many actions the activity feed keeps.
+const RECENT_ACTIONS_LISTED: usize = 200;
+/// How many recorded actions the timeline shows at most.
+const TIMELINE_ACTIONS_LISTED: usize = 300;
+
+/// Work for the thread keeping the storage analysis up to date.
+pub enum AnalysisUpdate {
+    /// An action of the file watcher to bring into the analysis.
+    Action(ActionEvent),
+    /// The analysis is done, the actions seen while it ran can be brought in.
+    Analysed,
+}
 
 glib::wrapper! {
     pub struct SmartShredsWindow(ObjectSubclass<imp::SmartShredsWindow>)
@@ -102,13 +139,19 @@ impl SmartShredsWindow {
             video_dir,
         ];
 
+        let largest_count = self.settings().uint("largest-count") as usize;
+        let large_file_min_size = self.settings().uint("large-file-min-size") as u64 * 1024 * 1024;
+        let stale_days: Vec<u32> = self.settings().get("stale-days");
         let (sender, receiver) = async_channel::unbounded();
 
         gio::spawn_blocking(move || {
-            let mut combined_analysis = StorageAnalysis::new();
+            let mut combined_analysis =
+                StorageAnalysis::with_largest(largest_count, large_file_min_size);
+            combined_analysis.stale_files = StaleReport::new(&stale_days);
             dirs_vec.iter().for_each(|dir| {
                 combined_analysis.analyse(&dir);
             });
+            combined_analysis.detect_junk_files(&dirs_vec);
             sender
                 .send_blocking(combined_analysis)
                 .expect("Error sending analysis");
@@ -126,25 +169,47 @@ impl SmartShredsWindow {
     }
 
     fn display_filetype_analysis(&self, analysis: StorageAnalysis) {
-        let recents_box = RecentsBox::new();
-        self.imp().recents_and_graph.append(&recents_box);
+        let plot = StorageAnalysisPlot::new();
+        plot.set_hexpand(true);
+        self.imp().recents_and_graph.append(&plot);
 
-        if analysis.file_types_info.is_empty() {
-            return;
+        self.display_file_types(&analysis);
+        self.display_largest(&analysis);
+        self.display_stale(analysis.stale_files.clone());
+        plot.set_tree(analysis.dir_tree.clone());
+        self.display_junk(analysis.junk_files.clone());
+        let mut shown = self
+            .imp()
+            .analysis
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *shown = Some(analysis);
+        if let Some(updates) = self.imp().analysis_updates.get() {
+            let _ = updates.try_send(AnalysisUpdate::Analysed);
+        }
+    }
+
+    /// A card for each type of file analysed.
+    fn display_file_types(&self, analysis: &StorageAnalysis) {
+        let file_type_boxes = &self.imp().file_type_boxes;
+        while let Some(child) = file_type_boxes.first_child() {
+            file_type_boxes.remove(&child);
         }
 
-        let mut names_for_background = vec![
+        // each type keeps its colour as the cards are updated
+        let names_for_background = [
             "faint-red",
             "faint-blue",
             "faint-green",
             "faint-yellow",
             "faint-purple",
-        ]
-        .into_iter();
-
-        for (file_type, (size, count)) in analysis.file_types_info.iter() {
+        ];
+        for (file_type, name) in FileType::ALL.iter().zip(names_for_background) {
+            let Some((size, count)) = analysis.file_types_info.get(file_type) else {
+                continue;
+            };
             let file_type_box = FileTypeBox::new();
-            file_type_box.set_property("name", names_for_background.next().expect("No more names"));
+            file_type_box.set_property("name", name);
             file_type_box
                 .imp()
                 .file_type_label
@@ -154,294 +219,2098 @@ impl SmartShredsWindow {
                 .imp()
                 .file_count_label
                 .set_label(&format!("{} items", count));
+            let capacity = analysis.capacity_of(file_type);
             file_type_box.imp().size_label.set_label(&format!(
                 "{} of {}",
                 format_size(*size),
-                format_size(analysis.total_device_memory)
+                format_size(capacity)
             ));
+            if capacity > 0 {
+                file_type_box
+                    .imp()
+                    .size_progress
+                    .set_fraction(*size as f64 / capacity as f64);
+            }
+            let disks: Vec<String> = analysis
+                .disks_of(file_type)
+                .map(|disk| {
+                    format!(
+                        "{}: {} used, {} available of {}",
+                        disk.mount_point.display(),
+                        format_size(disk.used()),
+                        format_size(disk.available),
+                        format_size(disk.total)
+                    )
+                })
+                .collect();
             file_type_box
                 .imp()
                 .size_progress
-                .set_fraction(*size as f64 / analysis.total_device_memory as f64);
+                .set_tooltip_text(Some(&disks.join("\n")));
             file_type_box
                 .imp()
                 .card_icon
                 .set_from_file(Some(file_type.get_image_icon()));
-            self.imp().file_type_boxes.append(&file_type_box);
+            file_type_boxes.append(&file_type_box);
         }
     }
 
-    fn save_auth_response_data(&self, auth_response: AuthResponse) {
-        self.settings()
-            .set_string("token", &auth_response.token)
-            .expect("Error setting token");
-        self.settings()
-            .set_string("user-id", &auth_response.user_id)
-            .expect("Error setting user-id");
-        self.settings()
-            .set_boolean("is-authenticated", true)
-            .expect("Error setting is-authenticated");
-        self.settings()
-            .set_boolean("first-time", false)
-            .expect("Error setting first-time");
-        self.settings()
-            .set_string("username", self.imp().signup_username.text().as_str())
-            .expect("Error setting username");
-        self.settings()
-            .set_string("email", self.imp().signup_email.text().as_str())
-            .expect("Error setting email");
+    /// Update the file type cards once the main loop is idle, so a burst of
+    /// actions only redraws them once.
+    fn queue_file_types_update(&self) {
+        if self.imp().file_types_queued.replace(true) {
+            return;
+        }
+        glib::idle_add_local_once(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move || {
+                window.imp().file_types_queued.set(false);
+                let analysis = window
+                    .imp()
+                    .analysis
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                if let Some(analysis) = analysis.as_ref() {
+                    window.display_file_types(analysis);
+                }
+            }
+        ));
     }
 
-    fn listen_recents(&self) {
-        let download_dir = dirs::download_dir().expect("No download directory");
-        let document_dir = dirs::document_dir().expect("No document directory");
-        let desktop_dir = dirs::desktop_dir().expect("No desktop directory");
-        let audio_dir = dirs::audio_dir().expect("No audio directory");
-        let video_dir = dirs::video_dir().expect("No video directory");
+    fn display_largest(&self, analysis: &StorageAnalysis) {
+        let largest_box = LargestBox::new();
+        let files = analysis.largest_files.sorted();
+        let folders = analysis.largest_dirs.sorted();
+        largest_box.imp().threshold_label.set_label(&format!(
+            "{} files and {} folders of at least {}",
+            files.len(),
+            folders.len(),
+            format_size(analysis.largest_files.min_size())
+        ));
+        for entry in files {
+            self.append_largest_row(&largest_box, &largest_box.imp().files_list, entry);
+        }
+        for entry in folders {
+            self.append_largest_row(&largest_box, &largest_box.imp().folders_list, entry);
+        }
 
-        let dirs_vec = vec![
-            document_dir,
-            desktop_dir,
-            audio_dir,
-            video_dir,
-            download_dir,
-        ];
+        largest_box.imp().trash_button.connect_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            largest_box,
+            move |_| window.run_largest_operation(&largest_box, FileOperation::Trash)
+        ));
+        largest_box.imp().move_button.connect_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            largest_box,
+            move |_| window.choose_largest_destination(&largest_box)
+        ));
+        self.update_largest_selection(&largest_box);
+        self.imp().file_reports.append(&largest_box);
+    }
 
-        gio::spawn_blocking(move || {
-            dirs_vec.iter().for_each(|dir| {
-                watch(dir);
-            });
-        });
+    fn append_largest_row(&self, largest_box: &LargestBox, list: &ListBox, entry: LargeEntry) {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        row.set_margin_top(5);
+        row.set_margin_bottom(5);
+        row.set_margin_start(10);
+        row.set_margin_end(10);
+
+        let check = CheckButton::new();
+        check.connect_toggled(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            largest_box,
+            move |_| window.update_largest_selection(&largest_box)
+        ));
+        let name = gtk::Label::new(entry.path.file_name().and_then(|name| name.to_str()));
+        name.set_halign(gtk::Align::Start);
+        name.set_hexpand(true);
+        name.set_ellipsize(pango::EllipsizeMode::Middle);
+        name.set_tooltip_text(Some(&entry.path.display().to_string()));
+        let kind = gtk::Label::new(Some(
+            entry.file_type.as_ref().map_or("Folder", |t| t.into()),
+        ));
+        kind.add_css_class("dim-label");
+        let accessed = gtk::Label::new(Some(&entry.accessed.format("%-m/%-d/%Y").to_string()));
+        accessed.add_css_class("dim-label");
+        accessed.set_tooltip_text(Some("Last accessed"));
+        let size = gtk::Label::new(Some(&format_size(entry.size)));
+        size.set_width_chars(10);
+        size.set_xalign(1.0);
+
+        row.append(&check);
+        row.append(&name);
+        row.append(&kind);
+        row.append(&accessed);
+        row.append(&size);
+        list.append(&row);
+        largest_box.imp().rows.borrow_mut().push((check, entry));
     }
 
-    fn duplicates(&self) -> gio::ListStore {
-        self.imp()
-            .duplicates
+    fn marked_largest(&self, largest_box: &LargestBox) -> Vec<LargeEntry> {
+        largest_box
+            .imp()
+            .rows
             .borrow()
-            .clone()
-            .expect("Could not get duplicates.")
+            .iter()
+            .filter(|(check, _)| check.is_active())
+            .map(|(_, entry)| entry.clone())
+            .collect()
     }
 
-    fn setup_duplicates(&self) {
-        let model = gio::ListStore::new::<DuplicateObject>();
-        self.imp().duplicates.replace(Some(model));
-        let filter_model = FilterListModel::new(Some(self.duplicates()), self.filters());
-        let selection_mode = MultiSelection::new(Some(filter_model));
-        self.imp().duplicates_list.set_model(Some(&selection_mode));
+    fn update_largest_selection(&self, largest_box: &LargestBox) {
+        let marked = self.marked_largest(largest_box);
+        let size: u64 = marked.iter().map(|entry| entry.size).sum();
+        largest_box.imp().selected_label.set_label(&format!(
+            "{} selected, {}",
+            marked.len(),
+            format_size(size)
+        ));
+        largest_box
+            .imp()
+            .move_button
+            .set_sensitive(!marked.is_empty());
+        largest_box
+            .imp()
+            .trash_button
+            .set_sensitive(!marked.is_empty());
     }
 
-    fn get_duplicates(&self) {
-        let download_dir = dirs::download_dir().expect("No download directory");
-        let document_dir = dirs::document_dir().expect("No document directory");
-        let desktop_dir = dirs::desktop_dir().expect("No desktop directory");
-        let picture_dir = dirs::picture_dir().expect("No picture directory");
-        let audio_dir = dirs::audio_dir().expect("No audio directory");
-        let video_dir = dirs::video_dir().expect("No video directory");
-
-        let dirs_vec = vec![
-            // document_dir,
-            download_dir,
-            // desktop_dir,
-            // picture_dir,
-            // audio_dir,
-            // video_dir,
-        ];
-
-        let (sender, receiver) = async_channel::unbounded();
-
-        gio::spawn_blocking(move || {
-            let mut final_duplicates_map: HashMap<String, Vec<DupFile>> = HashMap::new();
-            let mut final_total_file_count = 0;
-            let mut final_duplicates_size = 0;
-            let mut final_duplicates_count = 0;
+    /// Ask for the folder the marked large files are moved to.
+    fn choose_largest_destination(&self, largest_box: &LargestBox) {
+        let dialog = gtk::FileDialog::builder()
+            .title("Destination Folder")
+            .modal(true)
+            .build();
 
-            dirs_vec.iter().for_each(|dir| {
-                let (duplicates_map, total_file_count, duplicates_size, duplicates_count) =
-                    traverse_directory_for_duplicates(dir.clone());
-                final_duplicates_map.extend(duplicates_map);
-                final_total_file_count += total_file_count;
-                final_duplicates_size += duplicates_size;
-                final_duplicates_count += duplicates_count;
-            });
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            largest_box,
+            async move {
+                let Ok(folder) = dialog.select_folder_future(Some(&window)).await else {
+                    return;
+                };
+                if let Some(path) = folder.path() {
+                    window.run_largest_operation(&largest_box, FileOperation::Move(path));
+                }
+            }
+        ));
+    }
 
-            sender
-                .send_blocking((
-                    final_duplicates_map,
-                    final_total_file_count,
-                    final_duplicates_size,
-                    final_duplicates_count,
-                ))
-                .expect("Error sending duplicates");
-        });
+    fn run_largest_operation(&self, largest_box: &LargestBox, operation: FileOperation) {
+        let paths: Vec<PathBuf> = self
+            .marked_largest(largest_box)
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        largest_box.imp().move_button.set_sensitive(false);
+        largest_box.imp().trash_button.set_sensitive(false);
+        let receiver = self.spawn_file_operation(&operation, paths.clone());
 
         glib::spawn_future_local(clone!(
             #[weak(rename_to = window)]
             self,
+            #[weak]
+            largest_box,
             async move {
-                while let Ok((
-                    duplicates_map,
-                    total_file_count,
-                    duplicates_size,
-                    duplicates_count,
-                )) = receiver.recv().await
-                {
-                    window
-                        .imp()
-                        .files_scanned
-                        .set_label(&format_number(total_file_count));
-                    window
-                        .imp()
-                        .duplicates_count
-                        .set_label(&format_number(duplicates_count));
-
-                    window
-                        .imp()
-                        .duplicates_space_taken
-                        .set_label(&format_size(duplicates_size));
-
-                    let mut toggle = false;
-                    duplicates_map.into_iter().for_each(|(_, dup_files)| {
-                        let bgcolor = if toggle {
-                            "blue-duplicate-row"
-                        } else {
-                            "normal-duplicate-row"
-                        };
-                        dup_files.into_iter().for_each(|dup_file| {
-                            window.add_duplicate_row(dup_file, bgcolor);
-                        });
-                        toggle = !toggle;
-                    });
-                }
+                let Ok(results) = receiver.recv().await else {
+                    return;
+                };
+                let done = window.report_operation(&operation, paths, results);
+                window.remove_largest(&largest_box, &done);
             }
         ));
     }
 
-    fn add_duplicate_row(&self, dup_file: DupFile, bgcolor: &str) {
-        let formated_size = format_size(dup_file.file_size);
-        let formatted_date = dup_file
-            .date_created
-            .format("%-m/%-d/%Y %-I:%M:%S %p")
-            .to_string();
-        let duplicate_object = DuplicateObject::new(
-            dup_file.file_name,
-            formated_size,
-            dup_file.file_path.to_string_lossy().to_string(),
-            formatted_date,
-            bgcolor.to_string(),
-        );
+    /// Toast what a file operation did to `paths` and return the ones it was done to.
+    fn report_operation<T>(
+        &self,
+        operation: &FileOperation,
+        paths: Vec<PathBuf>,
+        results: Vec<std::io::Result<T>>,
+    ) -> Vec<PathBuf> {
+        let (done, failed) = partition_results(paths, results, PathBuf::clone);
+        let message = operation_status(operation, done.len(), failed.len());
+        self.imp().toastoverlay.add_toast(adw::Toast::new(&message));
+        done.into_iter().map(|(path, _)| path).collect()
+    }
 
-        self.duplicates().append(&duplicate_object);
+    fn display_stale(&self, report: StaleReport) {
+        let stale_box = StaleBox::new();
+        stale_box.imp().trash_button.connect_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            stale_box,
+            move |_| window.run_stale_operation(&stale_box, FileOperation::Trash)
+        ));
+        stale_box.imp().archive_button.connect_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            stale_box,
+            move |_| window.choose_archive_directory(&stale_box)
+        ));
+        self.update_stale_box(&stale_box, report);
+        self.imp().file_reports.append(&stale_box);
     }
 
-    fn setup_factory(&self) {
-        let factory = SignalListItemFactory::new();
+    /// List the files of `report` by age, the largest of each age first.
+    fn update_stale_box(&self, stale_box: &StaleBox, report: StaleReport) {
+        let buckets_box = stale_box.imp().buckets_box.get();
+        while let Some(child) = buckets_box.first_child() {
+            buckets_box.remove(&child);
+        }
+        let mut checks = Vec::new();
 
-        factory.connect_setup(move |_, list_item| {
-            let row = DuplicateRow::new();
-            list_item
-                .downcast_ref::<ListItem>()
-                .expect("Needs to be a `ListItem`.")
-                .set_child(Some(&row));
-        });
+        let since = report.buckets().first().map_or(0, |bucket| bucket.min_days);
+        stale_box.imp().summary_label.set_label(&format!(
+            "{} files, {} unused for over {} days",
+            format_number(report.count() as u64),
+            format_size(report.size()),
+            since
+        ));
+        for bucket in report.buckets().iter().rev() {
+            if bucket.files.is_empty() {
+                continue;
+            }
+            let header = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+            let bucket_check = self.stale_check(stale_box);
+            let label = gtk::Label::new(Some(&bucket.label()));
+            label.set_halign(gtk::Align::Start);
+            label.set_hexpand(true);
+            label.add_css_class("heading");
+            let count = gtk::Label::new(Some(&format!("{} files", bucket.files.len())));
+            count.add_css_class("dim-label");
+            header.append(&bucket_check);
+            header.append(&label);
+            header.append(&count);
+            header.append(&gtk::Label::new(Some(&format_size(bucket.size()))));
+            checks.push((bucket_check, bucket.files.clone()));
 
-        factory.connect_bind(move |_, list_item| {
-            let duplicate_object = list_item
-                .downcast_ref::<ListItem>()
-                .expect("Needs to be a `ListItem`.")
-                .item()
-                .and_downcast::<DuplicateObject>()
-                .expect("Needs to be a `DuplicateObject`.");
+            let files_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+            files_box.set_margin_start(20);
+            for file in bucket.largest(STALE_FILES_LISTED) {
+                let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+                let check = self.stale_check(stale_box);
+                let name = gtk::Label::new(file.path.file_name().and_then(|name| name.to_str()));
+                name.set_halign(gtk::Align::Start);
+                name.set_hexpand(true);
+                name.set_ellipsize(pango::EllipsizeMode::Middle);
+                name.set_tooltip_text(Some(&file.path.display().to_string()));
+                let last_used =
+                    gtk::Label::new(Some(&file.last_used.format("%-m/%-d/%Y").to_string()));
+                last_used.add_css_class("dim-label");
+                last_used.set_tooltip_text(Some(if file.modified_only {
+                    "Last modified, the disk does not record accesses"
+                } else {
+                    "Last used"
+                }));
+                let size = gtk::Label::new(Some(&format_size(file.size)));
+                size.set_width_chars(10);
+                size.set_xalign(1.0);
+                row.append(&check);
+                row.append(&name);
+                row.append(&last_used);
+                row.append(&size);
+                files_box.append(&row);
+                checks.push((check, vec![file]));
+            }
+            if bucket.files.len() > STALE_FILES_LISTED {
+                let more = gtk::Label::new(Some(&format!(
+                    "and {} smaller files",
+                    bucket.files.len() - STALE_FILES_LISTED
+                )));
+                more.set_halign(gtk::Align::Start);
+                more.add_css_class("dim-label");
+                files_box.append(&more);
+            }
 
-            let duplicate_row = list_item
-                .downcast_ref::<ListItem>()
-                .expect("Needs to be a `ListItem`.")
-                .child()
-                .and_downcast::<DuplicateRow>()
-                .expect("Needs to be a `DuplicateRow`.");
+            let expander = gtk::Expander::new(None);
+            expander.set_label_widget(Some(&header));
+            expander.set_child(Some(&files_box));
+            buckets_box.append(&expander);
+        }
 
-            duplicate_row.bind(&duplicate_object);
-        });
+        stale_box.imp().checks.replace(checks);
+        stale_box.imp().report.replace(report);
+        self.update_stale_selection(stale_box);
+    }
 
-        self.imp().duplicates_list.set_factory(Some(&factory));
+    fn stale_check(&self, stale_box: &StaleBox) -> CheckButton {
+        let check = CheckButton::new();
+        check.connect_toggled(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            stale_box,
+            move |_| window.update_stale_selection(&stale_box)
+        ));
+        check
     }
 
-    fn filters(&self) -> Option<CustomFilter> {
-        let filter_modes = self.imp().filter_modes.borrow().clone();
+    /// The files marked one by one or with their whole age group.
+    fn marked_stale(&self, stale_box: &StaleBox) -> Vec<StaleFile> {
+        let mut seen = HashSet::new();
+        stale_box
+            .imp()
+            .checks
+            .borrow()
+            .iter()
+            .filter(|(check, _)| check.is_active())
+            .flat_map(|(_, files)| files.iter())
+            .filter(|file| seen.insert(file.path.clone()))
+            .cloned()
+            .collect()
+    }
 
-        // if `filter_modes` is just empty, return `None`
-        if filter_modes.is_empty() {
-            return None;
+    fn update_stale_selection(&self, stale_box: &StaleBox) {
+        let marked = self.marked_stale(stale_box);
+        let size: u64 = marked.iter().map(|file| file.size).sum();
+        stale_box.imp().selected_label.set_label(&format!(
+            "{} selected, {}",
+            format_number(marked.len() as u64),
+            format_size(size)
+        ));
+        stale_box
+            .imp()
+            .archive_button
+            .set_sensitive(!marked.is_empty());
+        stale_box
+            .imp()
+            .trash_button
+            .set_sensitive(!marked.is_empty());
+    }
+
+    /// Ask for the folder the marked stale files are archived to, starting
+    /// from the last one used.
+    fn choose_archive_directory(&self, stale_box: &StaleBox) {
+        let dialog = gtk::FileDialog::builder()
+            .title("Archive Folder")
+            .modal(true)
+            .build();
+        let archive_directory = self.settings().string("archive-directory");
+        if !archive_directory.is_empty() {
+            dialog.set_initial_folder(Some(&gio::File::for_path(archive_directory.as_str())));
         }
 
-        let filter = CustomFilter::new(move |obj| {
-            let duplicate_object = obj
-                .downcast_ref::<DuplicateObject>()
-                .expect("Not a DuplicateObject");
-            let file_path: PathBuf = duplicate_object.path().into();
-            let file_extension = file_path
-                .extension()
-                .and_then(std::ffi::OsStr::to_str)
-                .unwrap_or("");
-            let duplicate_file_type = DuplicateFilterMode::from_extension(file_extension);
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            stale_box,
+            async move {
+                let Ok(folder) = dialog.select_folder_future(Some(&window)).await else {
+                    return;
+                };
+                if let Some(path) = folder.path() {
+                    window
+                        .settings()
+                        .set_string("archive-directory", &path.to_string_lossy())
+                        .expect("Error setting archive-directory");
+                    window.run_stale_operation(&stale_box, FileOperation::Move(path));
+                }
+            }
+        ));
+    }
 
-            // only allow duplicates that are of the same type as those in the filter_modes
-            filter_modes.contains(&duplicate_file_type)
-        });
+    fn run_stale_operation(&self, stale_box: &StaleBox, operation: FileOperation) {
+        let paths: Vec<PathBuf> = self
+            .marked_stale(stale_box)
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        stale_box.imp().archive_button.set_sensitive(false);
+        stale_box.imp().trash_button.set_sensitive(false);
+        let receiver = self.spawn_file_operation(&operation, paths.clone());
 
-        Some(filter)
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            stale_box,
+            async move {
+                let Ok(results) = receiver.recv().await else {
+                    return;
+                };
+                let done = window.report_operation(&operation, paths, results);
+                let mut report = stale_box.imp().report.take();
+                report.remove(&done);
+                window.update_stale_box(&stale_box, report);
+            }
+        ));
     }
 
-    fn apply_filters(&self) {
-        let filter_model = FilterListModel::new(Some(self.duplicates()), self.filters());
-        let selection_mode = MultiSelection::new(Some(filter_model));
-        self.imp().duplicates_list.set_model(Some(&selection_mode));
+    /// Drop the rows of the `removed` paths and of everything inside them.
+    fn remove_largest(&self, largest_box: &LargestBox, removed: &[PathBuf]) {
+        largest_box
+            .imp()
+            .rows
+            .borrow_mut()
+            .retain(|(check, entry)| {
+                if !removed.iter().any(|path| entry.path.starts_with(path)) {
+                    return true;
+                }
+                if let Some(row) = check
+                    .ancestor(ListBoxRow::static_type())
+                    .and_downcast::<ListBoxRow>()
+                {
+                    if let Some(list) = row.parent().and_downcast::<ListBox>() {
+                        list.remove(&row);
+                    }
+                }
+                false
+            });
+        self.update_largest_selection(largest_box);
+    }
 
-        let duplicates_count = selection_mode.n_items();
-        self.imp()
-            .duplicates_count
-            .set_label(&format_number(duplicates_count.into()));
+    fn display_junk(&self, junk_files: Vec<JunkFile>) {
+        let junk_box = JunkBox::new();
+        junk_box.set_property("name", "faint-grey");
+        junk_box.imp().clean_button.connect_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            junk_box,
+            move |_| window.clean_junk(&junk_box)
+        ));
+        self.update_junk_box(&junk_box, junk_files);
+        self.imp().file_type_boxes.append(&junk_box);
+    }
 
-        let duplicates = self.duplicates();
-        let mut size = 0;
-        let mut position = 0;
+    fn update_junk_box(&self, junk_box: &JunkBox, junk_files: Vec<JunkFile>) {
+        let categories_box = junk_box.imp().categories_box.get();
+        while let Some(child) = categories_box.first_child() {
+            categories_box.remove(&child);
+        }
+        for category in JunkFiles::ALL {
+            let size: u64 = junk_files
+                .iter()
+                .filter(|junk| junk.category == category)
+                .map(|junk| junk.size)
+                .sum();
+            if size == 0 {
+                continue;
+            }
+            let row = gtk::CenterBox::new();
+            row.set_start_widget(Some(&gtk::Label::new(Some(<&str>::from(&category)))));
+            row.set_end_widget(Some(&gtk::Label::new(Some(&format_size(size)))));
+            categories_box.append(&row);
+        }
 
-        while let Some(item) = duplicates.item(position) {
-            let duplicate_object = item
-                .downcast_ref::<DuplicateObject>()
-                .expect("Not a DuplicateObject");
-            let duplicate_size = duplicate_object
-                .size()
-                .split(" ")
-                .next()
-                .unwrap_or("0")
-                .parse::<u64>()
-                .unwrap_or(0);
-            size += duplicate_size;
-            position += 1;
-        }
-        // Todo: Fix this
-        self.imp()
-            .duplicates_space_taken
-            .set_label(&format_size(size));
+        let size: u64 = junk_files.iter().map(|junk| junk.size).sum();
+        junk_box
+            .imp()
+            .junk_count_label
+            .set_label(&format!("{} items", format_number(junk_files.len() as u64)));
+        junk_box.imp().size_label.set_label(&format_size(size));
+        junk_box
+            .imp()
+            .clean_button
+            .set_sensitive(!junk_files.is_empty());
+        junk_box.imp().junk_files.replace(junk_files);
     }
 
-    fn show_preview(&self) {
-        self.imp().duplicates_list.connect_activate(clone!(
+    /// Clean up the junk of a card off the main loop, keeping on the card what could not be removed.
+    fn clean_junk(&self, junk_box: &JunkBox) {
+        let junk_files = junk_box.imp().junk_files.take();
+        junk_box.imp().clean_button.set_sensitive(false);
+
+        let (sender, receiver) = async_channel::bounded(1);
+        gio::spawn_blocking(move || {
+            let results: Vec<std::io::Result<Option<PathBuf>>> =
+                junk_files.iter().map(JunkFile::clean).collect();
+            let entries: Vec<JournalEntry> = junk_files
+                .iter()
+                .zip(&results)
+                .filter_map(|(junk, result)| {
+                    let trashed = result.as_ref().ok()?.as_ref()?;
+                    Some(JournalEntry::new(
+                        OperationKind::Trash,
+                        junk.path.clone(),
+                        trashed.clone(),
+                    ))
+                })
+                .collect();
+            let description = format!("{} junk files moved to the Trash", entries.len());
+            update_journal(|journal| journal.record(description, entries));
+            sender
+                .send_blocking((junk_files, results))
+                .expect("Error sending junk cleanup results");
+        });
+
+        glib::spawn_future_local(clone!(
             #[weak(rename_to = window)]
             self,
-            move |_, i| {
-                let duplicates = window.duplicates();
-                if let Some(item) = duplicates.item(i) {
-                    let duplicate_object = item
-                        .downcast_ref::<DuplicateObject>()
-                        .expect("Not a DuplicateObject");
-                    let path = duplicate_object.path();
-                    let preview = Preview::new(path.into());
-                    if let Some(widget) = preview.widget() {
-                        window.imp().preview_viewport.set_child(Some(&widget));
-                    }
-                }
+            #[weak]
+            junk_box,
+            async move {
+                let Ok((junk_files, results)) = receiver.recv().await else {
+                    return;
+                };
+                let (cleaned, left) =
+                    partition_results(junk_files, results, |junk| junk.path.clone());
+                let cleaned: u64 = cleaned.iter().map(|(junk, _)| junk.size).sum();
+                let message = match left.len() {
+                    0 => format!("{} of junk cleaned up", format_size(cleaned)),
+                    failed => format!(
+                        "{} of junk cleaned up, {} items could not be removed",
+                        format_size(cleaned),
+                        failed
+                    ),
+                };
+                window
+                    .imp()
+                    .toastoverlay
+                    .add_toast(adw::Toast::new(&message));
+                window.update_junk_box(&junk_box, left);
             }
         ));
     }
+
+    fn save_auth_response_data(&self, auth_response: AuthResponse) {
+        self.settings()
+            .set_string("token", &auth_response.token)
+            .expect("Error setting token");
+        self.settings()
+            .set_string("user-id", &auth_response.user_id)
+            .expect("Error setting user-id");
+        self.settings()
+            .set_boolean("is-authenticated", true)
+            .expect("Error setting is-authenticated");
+        self.settings()
+            .set_boolean("first-time", false)
+            .expect("Error setting first-time");
+        self.settings()
+            .set_string("username", self.imp().signup_username.text().as_str())
+            .expect("Error setting username");
+        self.settings()
+            .set_string("email", self.imp().signup_email.text().as_str())
+            .expect("Error setting email");
+    }
+
+    fn listen_recents(&self) {
+        let recents_box = RecentsBox::new();
+        self.imp().recents_and_graph.append(&recents_box);
+        self.imp()
+            .recents_box
+            .set(recents_box)
+            .expect("Recents box already set");
+
+        // folders missing on this system are left out
+        let dirs_vec: Vec<PathBuf> = [
+            dirs::document_dir(),
+            dirs::desktop_dir(),
+            dirs::audio_dir(),
+            dirs::video_dir(),
+            dirs::download_dir(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let periods = gtk::StringList::new(&Period::ALL.map(|period| (&period).into()));
+        let timeline_period = &self.recents_box().imp().timeline_period;
+        timeline_period.set_model(Some(&periods));
+        timeline_period.set_selected(2);
+        timeline_period.connect_selected_notify(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| window.queue_timeline_update()
+        ));
+        self.setup_recents_filters();
+
+        let (history_sender, history_receiver) = async_channel::bounded(1);
+        gio::spawn_blocking(move || {
+            history_sender
+                .send_blocking(ActivityHistory::open())
+                .expect("Error sending activity history");
+        });
+
+        // the analysis is updated away from the main loop, one action after the other,
+        // on a thread of its own rather than one of the pool for short-lived work
+        let (update_sender, update_receiver) = async_channel::unbounded();
+        let (updated_sender, updated_receiver) = async_channel::unbounded();
+        let analysis = self.imp().analysis.clone();
+        std::thread::spawn(move || {
+            // actions made while the analysis runs wait for it, they may be missing from it
+            let mut waiting = Vec::new();
+            let mut analysed = false;
+            while let Ok(update) = update_receiver.recv_blocking() {
+                let action_events = match update {
+                    AnalysisUpdate::Action(action_event) if !analysed => {
+                        waiting.push(action_event);
+                        continue;
+                    }
+                    AnalysisUpdate::Action(action_event) => vec![action_event],
+                    AnalysisUpdate::Analysed => {
+                        analysed = true;
+                        std::mem::take(&mut waiting)
+                    }
+                };
+                for action_event in action_events {
+                    // the disk is read before locking, the main loop draws from the analysis
+                    let delta = AnalysisDelta::of(&action_event.action, &action_event.file_path);
+                    let mut analysis = analysis.lock().unwrap_or_else(|e| e.into_inner());
+                    if let Some(analysis) = analysis.as_mut() {
+                        analysis.merge(delta);
+                    }
+                }
+                if updated_sender.send_blocking(()).is_err() {
+                    return;
+                }
+            }
+        });
+        self.imp()
+            .analysis_updates
+            .set(update_sender.clone())
+            .expect("Analysis updates already set");
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                while updated_receiver.recv().await.is_ok() {
+                    window.queue_file_types_update();
+                }
+            }
+        ));
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                // the watcher starts once the history is loaded, so no action is missed
+                if let Ok(history) = history_receiver.recv().await {
+                    window.imp().activity_history.replace(history);
+                    window.queue_timeline_update();
+                }
+
+                // the watcher runs for the whole session, it gets a thread of its own too
+                let (sender, receiver) = async_channel::unbounded();
+                std::thread::spawn(move || watch(&dirs_vec, sender));
+                while let Ok(action_event) = receiver.recv().await {
+                    let _ = update_sender
+                        .send(AnalysisUpdate::Action(action_event.clone()))
+                        .await;
+                    window.add_recent_action(action_event);
+                }
+            }
+        ));
+    }
+
+    fn recents_box(&self) -> &RecentsBox {
+        self.imp().recents_box.get().expect("Recents box not set")
+    }
+
+    fn setup_recents_filters(&self) {
+        let imp = self.recents_box().imp();
+        let kinds: Vec<&str> = std::iter::once("All actions")
+            .chain(ActionKind::ALL.iter().map(|kind| action_kind_label(*kind)))
+            .collect();
+        imp.kind_filter
+            .set_model(Some(&gtk::StringList::new(&kinds)));
+        imp.node_filter.set_model(Some(&gtk::StringList::new(&[
+            "Files and folders",
+            "Files",
+            "Folders",
+        ])));
+        for drop_down in [&imp.kind_filter, &imp.node_filter] {
+            drop_down.connect_selected_notify(clone!(
+                #[weak(rename_to = window)]
+                self,
+                move |_| {
+                    window.recents_box().imp().feed_list.invalidate_filter();
+                    window.queue_timeline_update();
+                }
+            ));
+        }
+
+        imp.feed_list.set_filter_func(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[upgrade_or]
+            true,
+            move |row| {
+                let filter = window.action_filter();
+                let feed = window.recents_box().imp().feed.borrow();
+                feed.get(row.index() as usize).is_none_or(|action_event| {
+                    filter.matches(action_event.action.kind(), action_event.node)
+                })
+            }
+        ));
+    }
+
+    /// The actions chosen in the filters of the Recents panel.
+    fn action_filter(&self) -> ActionFilter {
+        let imp = self.recents_box().imp();
+        let kind = match imp.kind_filter.selected() {
+            0 => None,
+            i => ActionKind::ALL.get(i as usize - 1).copied(),
+        };
+        let node = match imp.node_filter.selected() {
+            1 => Some(Node::File),
+            2 => Some(Node::Folder),
+            _ => None,
+        };
+        ActionFilter { kind, node }
+    }
+
+    /// Put an action at the top of the activity feed, dropping the oldest ones,
+    /// and record it in the history.
+    fn add_recent_action(&self, action_event: ActionEvent) {
+        let record = ActivityRecord::of(&action_event);
+        if let Err(e) = self.imp().activity_history.borrow_mut().record(record) {
+            eprintln!("Error saving activity history: {}", e);
+        }
+        self.queue_timeline_update();
+
+        let list = &self.recents_box().imp().feed_list;
+        let feed = &self.recents_box().imp().feed;
+
+        let row = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        row.set_margin_top(5);
+        row.set_margin_bottom(5);
+        row.set_margin_start(10);
+        row.set_margin_end(10);
+        row.set_tooltip_text(Some(&action_event.file_path.display().to_string()));
+
+        let description = gtk::Label::new(Some(&action_text(
+            &action_event.action,
+            action_event.node,
+            &action_event.file_path,
+        )));
+        description.set_halign(gtk::Align::Start);
+        description.set_xalign(0.0);
+        description.set_wrap(true);
+        let folder = action_event
+            .file_path
+            .parent()
+            .map_or(String::new(), |parent| parent.display().to_string());
+        let details = gtk::Label::new(Some(&format!(
+            "{}  ·  {}",
+            action_event.time.with_timezone(&Local).format("%H:%M:%S"),
+            folder
+        )));
+        details.set_halign(gtk::Align::Start);
+        details.set_ellipsize(pango::EllipsizeMode::Middle);
+        details.add_css_class("dim-label");
+        details.add_css_class("caption");
+
+        row.append(&description);
+        row.append(&details);
+        // the filter looks the action of a row up by its index
+        feed.borrow_mut().push_front(action_event);
+        list.prepend(&row);
+        while let Some(oldest) = list.row_at_index(RECENT_ACTIONS_LISTED as i32) {
+            list.remove(&oldest);
+            feed.borrow_mut().pop_back();
+        }
+    }
+
+    /// Update the timeline once the main loop is idle, so a burst of actions
+    /// only redraws it once.
+    fn queue_timeline_update(&self) {
+        if self.imp().timeline_queued.replace(true) {
+            return;
+        }
+        glib::idle_add_local_once(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move || {
+                window.imp().timeline_queued.set(false);
+                window.display_timeline();
+            }
+        ));
+    }
+
+    /// Show the recorded actions of the chosen period, one list per day.
+    fn display_timeline(&self) {
+        let recents_box = self.recents_box();
+        let timeline_box = &recents_box.imp().timeline_box;
+        while let Some(child) = timeline_box.first_child() {
+            timeline_box.remove(&child);
+        }
+
+        let period = Period::ALL
+            .get(recents_box.imp().timeline_period.selected() as usize)
+            .copied()
+            .unwrap_or(Period::Week);
+        let filter = self.action_filter();
+        let history = self.imp().activity_history.borrow();
+        let records: Vec<&ActivityRecord> = history
+            .changes(period, None)
+            .into_iter()
+            .filter(|record| filter.matches(record.action, record.node))
+            .collect();
+        if records.is_empty() {
+            let empty = gtk::Label::new(Some("No activity in this period"));
+            empty.set_margin_top(20);
+            empty.add_css_class("dim-label");
+            timeline_box.append(&empty);
+            return;
+        }
+
+        let today = Local::now().date_naive();
+        let mut listed = 0;
+        for (day, day_records) in timeline(&records) {
+            if listed >= TIMELINE_ACTIONS_LISTED {
+                break;
+            }
+            let header = gtk::Label::new(Some(&day_label(day, today)));
+            header.set_halign(gtk::Align::Start);
+            header.set_margin_top(5);
+            header.set_margin_start(5);
+            header.add_css_class("heading");
+            let list = ListBox::new();
+            list.set_selection_mode(gtk::SelectionMode::None);
+            list.add_css_class("boxed-list");
+
+            for record in day_records.iter().take(TIMELINE_ACTIONS_LISTED - listed) {
+                let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+                row.set_margin_top(5);
+                row.set_margin_bottom(5);
+                row.set_margin_start(10);
+                row.set_margin_end(10);
+                row.set_tooltip_text(Some(&record.path.display().to_string()));
+                let time = gtk::Label::new(Some(
+                    &record
+                        .time()
+                        .with_timezone(&Local)
+                        .format("%H:%M")
+                        .to_string(),
+                ));
+                time.add_css_class("dim-label");
+                let description = gtk::Label::new(Some(&action_text(
+                    &record.to_action(),
+                    record.node,
+                    &record.path,
+                )));
+                description.set_halign(gtk::Align::Start);
+                description.set_hexpand(true);
+                description.set_ellipsize(pango::EllipsizeMode::Middle);
+
+                row.append(&time);
+                row.append(&description);
+                list.append(&row);
+                listed += 1;
+            }
+            timeline_box.append(&header);
+            timeline_box.append(&list);
+        }
+    }
+
+    fn duplicates(&self) -> gio::ListStore {
+        self.imp()
+            .duplicates
+            .borrow()
+            .clone()
+            .expect("Could not get duplicates.")
+    }
+
+    fn setup_duplicates(&self) {
+        let model = gio::ListStore::new::<DuplicateObject>();
+        self.imp().duplicates.replace(Some(model));
+        self.imp()
+            .duplicates_list
+            .set_model(Some(&self.duplicates_model()));
+        self.load_keep_rules();
+    }
+
+    fn keep_rules(&self) -> Vec<KeepRule> {
+        self.settings()
+            .strv("keep-rules")
+            .iter()
+            .filter_map(|rule| {
+                let parsed = KeepRule::parse(rule.as_str());
+                if parsed.is_none() {
+                    eprintln!("Ignoring unknown keep rule {:?}", rule.as_str());
+                }
+                parsed
+            })
+            .collect()
+    }
+
+    fn save_keep_rules(&self, rules: &[KeepRule]) {
+        let rules: Vec<String> = rules.iter().map(|rule| rule.to_string()).collect();
+        let rules: Vec<&str> = rules.iter().map(String::as_str).collect();
+        self.settings()
+            .set_strv("keep-rules", rules.as_slice())
+            .expect("Error setting keep-rules");
+        self.display_keep_rules();
+    }
+
+    /// The keep rule checks of the Auto Select popover with the rule each one stands for.
+    fn keep_rule_checks(&self) -> Vec<(CheckButton, Option<KeepRule>)> {
+        let imp = self.imp();
+        let preferred_directory = self.settings().string("keep-preferred-directory");
+        vec![
+            (imp.keep_newest_check.get(), Some(KeepRule::Newest)),
+            (imp.keep_oldest_check.get(), Some(KeepRule::Oldest)),
+            (
+                imp.keep_shortest_path_check.get(),
+                Some(KeepRule::ShortestPath),
+            ),
+            (
+                imp.keep_not_in_downloads_check.get(),
+                Some(KeepRule::NotInDownloads),
+            ),
+            (
+                imp.keep_preferred_directory_check.get(),
+                (!preferred_directory.is_empty())
+                    .then(|| KeepRule::PreferredDirectory(preferred_directory.as_str().into())),
+            ),
+        ]
+    }
+
+    fn load_keep_rules(&self) {
+        let rules = self.keep_rules();
+        self.imp().loading_keep_rules.set(true);
+        for (check, rule) in self.keep_rule_checks() {
+            check.set_active(rule.is_some_and(|rule| rules.contains(&rule)));
+        }
+        self.imp().loading_keep_rules.set(false);
+        self.display_keep_rules();
+    }
+
+    /// Add or remove the rule of a check. A rule that is turned on has the lowest priority.
+    fn keep_rule_toggled(&self, toggled: &CheckButton) {
+        if self.imp().loading_keep_rules.get() {
+            return;
+        }
+        // keeping the newest and the oldest copy contradict each other
+        if toggled.is_active() {
+            let imp = self.imp();
+            if toggled == &imp.keep_newest_check.get() {
+                imp.keep_oldest_check.set_active(false);
+            } else if toggled == &imp.keep_oldest_check.get() {
+                imp.keep_newest_check.set_active(false);
+            }
+        }
+
+        let checks = self.keep_rule_checks();
+        if toggled == &self.imp().keep_preferred_directory_check.get()
+            && toggled.is_active()
+            && checks.last().is_some_and(|(_, rule)| rule.is_none())
+        {
+            self.choose_preferred_directory();
+            return;
+        }
+
+        let active: Vec<KeepRule> = checks
+            .into_iter()
+            .filter(|(check, _)| check.is_active())
+            .filter_map(|(_, rule)| rule)
+            .collect();
+        let mut rules: Vec<KeepRule> = self
+            .keep_rules()
+            .into_iter()
+            .filter(|rule| active.contains(rule))
+            .collect();
+        for rule in active {
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+        self.save_keep_rules(&rules);
+    }
+
+    /// Ask for the folder whose copies the preferred folder rule keeps.
+    fn choose_preferred_directory(&self) {
+        let dialog = gtk::FileDialog::builder()
+            .title("Preferred Folder")
+            .modal(true)
+            .build();
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                let folder = match dialog.select_folder_future(Some(&window)).await {
+                    Ok(folder) => folder,
+                    Err(_) => {
+                        // dismissed: the rule cannot apply without a folder
+                        if window
+                            .settings()
+                            .string("keep-preferred-directory")
+                            .is_empty()
+                        {
+                            window
+                                .imp()
+                                .keep_preferred_directory_check
+                                .set_active(false);
+                        }
+                        return;
+                    }
+                };
+                let Some(path) = folder.path() else {
+                    return;
+                };
+                window
+                    .settings()
+                    .set_string("keep-preferred-directory", &path.to_string_lossy())
+                    .expect("Error setting keep-preferred-directory");
+
+                // the new folder takes the place of the old one in the rules
+                let preferred = KeepRule::PreferredDirectory(path);
+                let mut rules = window.keep_rules();
+                match rules
+                    .iter()
+                    .position(|rule| matches!(rule, KeepRule::PreferredDirectory(_)))
+                {
+                    Some(position) => rules[position] = preferred,
+                    None => rules.push(preferred),
+                }
+                window.save_keep_rules(&rules);
+                window.load_keep_rules();
+            }
+        ));
+    }
+
+    fn display_keep_rules(&self) {
+        let rules: Vec<String> = self
+            .keep_rules()
+            .iter()
+            .map(|rule| rule.description())
+            .collect();
+        let label = if rules.is_empty() {
+            "Without rules the first copy of every group is kept.".to_string()
+        } else {
+            format!("Keeps the copy that is {}.", rules.join(", then "))
+        };
+        self.imp().keep_rules_label.set_label(&label);
+    }
+
+    /// Check every copy of each group of identical files or folders but the one
+    /// the keep rules choose to keep.
+    fn apply_keep_rules(&self) {
+        let rules = self.keep_rules();
+        let duplicates = self.duplicates();
+        let identical = [
+            DuplicateKind::Exact.name(),
+            DuplicateKind::Directories.name(),
+        ];
+        let mut groups: HashMap<String, Vec<(DuplicateObject, DupFile)>> = HashMap::new();
+        for position in 0..duplicates.n_items() {
+            let Some(duplicate_object) =
+                duplicates.item(position).and_downcast::<DuplicateObject>()
+            else {
+                continue;
+            };
+            // near-duplicates are distinct files, none of them is a spare copy
+            if !identical.contains(&duplicate_object.kind().as_str()) {
+                continue;
+            }
+            if let Some(dup_file) = duplicate_object.dup_file() {
+                groups
+                    .entry(duplicate_object.group())
+                    .or_default()
+                    .push((duplicate_object, dup_file));
+            }
+        }
+
+        for group in groups.into_values() {
+            let files: Vec<DupFile> = group.iter().map(|(_, file)| file.clone()).collect();
+            let keeper = choose_keeper(&files, &rules);
+            for (position, (duplicate_object, _)) in group.into_iter().enumerate() {
+                duplicate_object.set_check(Some(position) != keeper);
+            }
+        }
+    }
+
+    /// The duplicates shown in the list, filtered and optionally sorted by similarity.
+    /// Duplicate directories can be expanded to show their contents.
+    fn duplicates_model(&self) -> MultiSelection {
+        let filter_model = FilterListModel::new(Some(self.duplicates()), self.filters());
+        let tree_model = |model: gio::ListModel| {
+            let tree_model = TreeListModel::new(model, false, false, |item| {
+                item.downcast_ref::<DuplicateObject>()
+                    .expect("Not a DuplicateObject")
+                    .contents()
+                    .and_upcast()
+            });
+            MultiSelection::new(Some(tree_model))
+        };
+        if !self.imp().sort_by_similarity_button.is_active() {
+            return tree_model(filter_model.upcast());
+        }
+
+        let sorter = CustomSorter::new(|a, b| {
+            let a = a
+                .downcast_ref::<DuplicateObject>()
+                .expect("Not a DuplicateObject");
+            let b = b
+                .downcast_ref::<DuplicateObject>()
+                .expect("Not a DuplicateObject");
+            // most similar first, files of the same group next to each other
+            b.similarity()
+                .total_cmp(&a.similarity())
+                .then_with(|| a.group().cmp(&b.group()))
+                .then_with(|| a.path().cmp(&b.path()))
+                .into()
+        });
+        tree_model(SortListModel::new(Some(filter_model), Some(sorter)).upcast())
+    }
+
+    fn get_duplicates(&self) {
+        self.cancel_scan();
+        self.duplicates().remove_all();
+        self.queue_files_marked_update();
+        self.imp().space_reclaimed.set(0);
+        self.imp()
+            .duplicates_space_reclaimed
+            .set_label(&format_size(0));
+
+        let dirs_vec: Vec<PathBuf> = [
+            dirs::document_dir(),
+            dirs::download_dir(),
+            dirs::desktop_dir(),
+            dirs::picture_dir(),
+            dirs::audio_dir(),
+            dirs::video_dir(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let similar_images = self
+            .settings()
+            .boolean("similar-images")
+            .then(|| ImageSimilarity {
+                algorithm: PerceptualHash::from(
+                    self.settings().string("image-hash-algorithm").as_str(),
+                ),
+                max_distance: self.settings().uint("image-hash-threshold"),
+            });
+        let options = ScanOptions {
+            follow_symlinks: self.settings().boolean("follow-symlinks"),
+            similar_images,
+            similar_documents: self.settings().boolean("similar-documents").then(|| {
+                DocumentSimilarity {
+                    min_similarity: self.settings().double("document-similarity-threshold"),
+                }
+            }),
+        };
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.imp().scan_cancelled.replace(Some(cancelled.clone()));
+        self.imp().cancel_scan_button.set_sensitive(true);
+        self.imp().scan_status.set_label("Scanning...");
+
+        let (sender, receiver) = async_channel::unbounded();
+
+        gio::spawn_blocking(clone!(
+            #[strong]
+            cancelled,
+            move || {
+                let cache = Mutex::new(HashCache::open());
+                let scan = traverse_directories_for_duplicates(
+                    &dirs_vec,
+                    &options,
+                    &cache,
+                    &cancelled,
+                    |event| {
+                        // the receiver is gone once the window is closed
+                        let _ = sender.send_blocking(event);
+                    },
+                );
+                let _ = sender.send_blocking(ScanEvent::Finished(scan));
+
+                let mut cache = cache.into_inner().expect("Hash cache poisoned");
+                cache.compact();
+                if let Err(e) = cache.save() {
+                    eprintln!("Error saving hash cache: {}", e);
+                }
+            }
+        ));
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                let mut toggle = false;
+                while let Ok(event) = receiver.recv().await {
+                    // events of a scan replaced by a newer one must not leak into it
+                    let superseded = window
+                        .imp()
+                        .scan_cancelled
+                        .borrow()
+                        .as_ref()
+                        .is_some_and(|current| !Arc::ptr_eq(current, &cancelled));
+                    if superseded {
+                        break;
+                    }
+
+                    // a cancelled scan winds down until it reports its partial summary
+                    match event {
+                        ScanEvent::Progress(_) if cancelled.load(Ordering::Relaxed) => {}
+                        ScanEvent::Progress(progress) => window.display_scan_progress(&progress),
+                        ScanEvent::Group(hash, dup_files) => {
+                            let bgcolor = if toggle {
+                                "blue-duplicate-row"
+                            } else {
+                                "normal-duplicate-row"
+                            };
+                            dup_files.into_iter().for_each(|dup_file| {
+                                window.add_duplicate_row(
+                                    dup_file,
+                                    bgcolor,
+                                    &hash,
+                                    DuplicateKind::Exact,
+                                    1.0,
+                                );
+                            });
+                            toggle = !toggle;
+                        }
+                        ScanEvent::Similar(group) => {
+                            let bgcolor = if toggle {
+                                "blue-duplicate-row"
+                            } else {
+                                "normal-duplicate-row"
+                            };
+                            window.add_similar_rows(group, bgcolor);
+                            toggle = !toggle;
+                        }
+                        ScanEvent::Directories(group) => {
+                            let bgcolor = if toggle {
+                                "blue-duplicate-row"
+                            } else {
+                                "normal-duplicate-row"
+                            };
+                            window.add_directory_rows(group, bgcolor);
+                            toggle = !toggle;
+                        }
+                        ScanEvent::Finished(scan) => window.display_scan_finished(&scan),
+                    }
+                }
+            }
+        ));
+    }
+
+    /// Stop the running duplicate scan, keeping the groups found so far.
+    fn cancel_scan(&self) {
+        if let Some(cancelled) = self.imp().scan_cancelled.take() {
+            cancelled.store(true, Ordering::Relaxed);
+            self.imp().scan_status.set_label("Scan cancelled");
+        }
+        self.imp().cancel_scan_button.set_sensitive(false);
+    }
+
+    fn display_scan_progress(&self, progress: &ScanProgress) {
+        self.imp()
+            .files_scanned
+            .set_label(&format_number(progress.files_discovered));
+        self.imp()
+            .duplicates_count
+            .set_label(&format_number(self.duplicates().n_items().into()));
+        self.imp().scan_status.set_label(&format!(
+            "{} groups found, {} hashed {}",
+            progress.groups_found,
+            format_size(progress.bytes_hashed),
+            progress.current_path.display()
+        ));
+    }
+
+    fn display_scan_finished(&self, scan: &DuplicateScan) {
+        self.imp().scan_cancelled.replace(None);
+        self.imp().cancel_scan_button.set_sensitive(false);
+        self.imp()
+            .files_scanned
+            .set_label(&format_number(scan.total_file_count));
+        self.imp()
+            .duplicates_count
+            .set_label(&format_number(scan.duplicates_count));
+        self.imp()
+            .duplicates_space_taken
+            .set_label(&format_size(scan.reclaimable_size));
+        let similar_count = |kind| {
+            let count = scan
+                .similar
+                .iter()
+                .filter(|group| group.kind == kind)
+                .count();
+            format_number(count as u64)
+        };
+        self.imp().scan_status.set_label(&format!(
+            "{}{} groups found, {} duplicate folders ({} reclaimable), {} of similar images, {} of similar documents, {} sets of hardlinks already deduplicated",
+            if scan.cancelled { "Scan cancelled: " } else { "" },
+            format_number(scan.duplicates.len() as u64),
+            format_number(scan.directories.len() as u64),
+            format_size(scan.directories.iter().map(|group| group.reclaimable_size()).sum()),
+            similar_count(DuplicateKind::SimilarImages),
+            similar_count(DuplicateKind::SimilarDocuments),
+            format_number(scan.hardlinks.len() as u64)
+        ));
+    }
+
+    /// Add the files of a group of near-duplicates, named after the first of them.
+    fn add_similar_rows(&self, group: SimilarGroup, bgcolor: &str) {
+        let Some(first) = group.files.first() else {
+            return;
+        };
+        let group_name = format!(
+            "{}:{}",
+            group.kind.name(),
+            first.file.file_path.to_string_lossy()
+        );
+        group.files.into_iter().for_each(|similar_file| {
+            self.add_duplicate_row(
+                similar_file.file,
+                bgcolor,
+                &group_name,
+                group.kind,
+                similar_file.similarity,
+            );
+        });
+    }
+
+    /// Add the directories of a duplicate tree in place of the groups of files it covers.
+    fn add_directory_rows(&self, group: DirectoryGroup, bgcolor: &str) {
+        let duplicates = self.duplicates();
+        let mut position = duplicates.n_items();
+        while position > 0 {
+            position -= 1;
+            let covered = duplicates
+                .item(position)
+                .and_downcast::<DuplicateObject>()
+                .is_some_and(|object| group.covered_groups.contains(&object.group()));
+            if covered {
+                duplicates.remove(position);
+            }
+        }
+        self.queue_files_marked_update();
+
+        let Some(first) = group.dirs.first() else {
+            return;
+        };
+        let group_name = format!(
+            "{}:{}",
+            DuplicateKind::Directories.name(),
+            first.file_path.to_string_lossy()
+        );
+        let file_count = format_number(group.file_count);
+        group.dirs.into_iter().for_each(|dir| {
+            let duplicate_object =
+                self.add_duplicate_row(dir, bgcolor, &group_name, DuplicateKind::Directories, 1.0);
+            duplicate_object.set_size(format!("{}, {} files", duplicate_object.size(), file_count));
+        });
+    }
+
+    fn add_duplicate_row(
+        &self,
+        dup_file: DupFile,
+        bgcolor: &str,
+        group: &str,
+        kind: DuplicateKind,
+        similarity: f64,
+    ) -> DuplicateObject {
+        let formated_size = format_size(dup_file.file_size);
+        let formatted_date = dup_file
+            .date_created
+            .format("%-m/%-d/%Y %-I:%M:%S %p")
+            .to_string();
+        let duplicate_object = DuplicateObject::new(
+            dup_file.file_name.clone(),
+            formated_size,
+            dup_file.file_path.to_string_lossy().to_string(),
+            formatted_date,
+            bgcolor.to_string(),
+            group.to_string(),
+            similarity,
+        );
+        duplicate_object.set_kind(kind.name());
+        duplicate_object.set_is_directory(kind == DuplicateKind::Directories);
+        duplicate_object.set_dup_file(dup_file);
+        duplicate_object.connect_check_notify(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| window.queue_files_marked_update()
+        ));
+
+        self.duplicates().append(&duplicate_object);
+        duplicate_object
+    }
+
+    /// The rows whose check is set.
+    fn marked_duplicates(&self) -> Vec<DuplicateObject> {
+        let duplicates = self.duplicates();
+        (0..duplicates.n_items())
+            .filter_map(|position| duplicates.item(position).and_downcast::<DuplicateObject>())
+            .filter(|duplicate_object| duplicate_object.check())
+            .collect()
+    }
+
+    /// Update `files_marked` once the main loop is idle, so checking many rows at
+    /// once only counts them once.
+    fn queue_files_marked_update(&self) {
+        if self.imp().files_marked_queued.replace(true) {
+            return;
+        }
+        glib::idle_add_local_once(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move || {
+                window.imp().files_marked_queued.set(false);
+                window.display_files_marked();
+            }
+        ));
+    }
+
+    fn display_files_marked(&self) {
+        let marked = self.marked_duplicates();
+        let size: u64 = marked
+            .iter()
+            .filter_map(|duplicate_object| duplicate_object.dup_file())
+            .map(|dup_file| dup_file.file_size)
+            .sum();
+        self.imp().files_marked.set_label(&format!(
+            "{} files selected, total size of {}",
+            format_number(marked.len() as u64),
+            format_size(size)
+        ));
+    }
+
+    /// Ask for the folder the marked files are moved or copied to.
+    fn choose_destination(&self, operation: fn(PathBuf) -> FileOperation) {
+        if self.marked_duplicates().is_empty() {
+            return;
+        }
+        let dialog = gtk::FileDialog::builder()
+            .title("Destination Folder")
+            .modal(true)
+            .build();
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                let Ok(folder) = dialog.select_folder_future(Some(&window)).await else {
+                    return;
+                };
+                if let Some(path) = folder.path() {
+                    window.run_file_operation(operation(path));
+                }
+            }
+        ));
+    }
+
+    /// Ask for confirmation and how to overwrite them, then shred the marked files.
+    fn shred_marked(&self) {
+        let marked = self.marked_duplicates();
+        if marked.is_empty() {
+            return;
+        }
+        let mut warnings = Vec::new();
+        for duplicate_object in &marked {
+            for warning in shred_warnings(Path::new(&duplicate_object.path())) {
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+        }
+
+        let mut body = format!(
+            "The content of {} files is overwritten before they are removed. They cannot be restored.",
+            marked.len()
+        );
+        for warning in &warnings {
+            body.push_str(&format!("\n\n{}.", warning));
+        }
+        let dialog = adw::AlertDialog::new(Some("Shred Files?"), Some(&body));
+        dialog.add_responses(&[("cancel", "Cancel"), ("shred", "Shred")]);
+        dialog.set_response_appearance("shred", adw::ResponseAppearance::Destructive);
+        dialog.set_default_response(Some("cancel"));
+        dialog.set_close_response("cancel");
+
+        let modes = gtk::StringList::new(&ShredMode::ALL.map(|mode| mode.description()));
+        let mode_drop_down = gtk::DropDown::new(Some(modes), gtk::Expression::NONE);
+        let mode = ShredMode::parse(&self.settings().string("shred-mode")).unwrap_or_default();
+        let position = ShredMode::ALL.iter().position(|m| *m == mode).unwrap_or(0);
+        mode_drop_down.set_selected(position as u32);
+        dialog.set_extra_child(Some(&mode_drop_down));
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                if dialog.choose_future(&window).await != "shred" {
+                    return;
+                }
+                let mode = ShredMode::ALL[mode_drop_down.selected() as usize];
+                window
+                    .settings()
+                    .set_string("shred-mode", &mode.to_string())
+                    .expect("Error setting shred-mode");
+                window.run_file_operation(FileOperation::Shred(mode));
+            }
+        ));
+    }
+
+    /// Apply `operation` to every marked file off the main loop.
+    fn run_file_operation(&self, operation: FileOperation) {
+        let marked = self.marked_duplicates();
+        if marked.is_empty() {
+            return;
+        }
+        let paths: Vec<PathBuf> = marked
+            .iter()
+            .map(|duplicate_object| duplicate_object.path().into())
+            .collect();
+        let receiver = self.spawn_file_operation(&operation, paths);
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                if let Ok(results) = receiver.recv().await {
+                    window.file_operation_finished(&operation, marked, results);
+                }
+            }
+        ));
+    }
+
+    /// Apply `operation` to `paths` off the main loop and record it in the journal.
+    fn spawn_file_operation(
+        &self,
+        operation: &FileOperation,
+        paths: Vec<PathBuf>,
+    ) -> async_channel::Receiver<Vec<std::io::Result<PathBuf>>> {
+        let operation = operation.clone();
+        let (sender, receiver) = async_channel::bounded(1);
+        gio::spawn_blocking(clone!(
+            #[strong]
+            operation,
+            move || {
+                let results: Vec<std::io::Result<PathBuf>> =
+                    paths.iter().map(|path| operation.apply(path)).collect();
+                if let Some(kind) = OperationKind::of(&operation) {
+                    let entries: Vec<JournalEntry> = paths
+                        .iter()
+                        .zip(&results)
+                        .filter_map(|(path, result)| {
+                            let destination = result.as_ref().ok()?;
+                            Some(JournalEntry::new(kind, path.clone(), destination.clone()))
+                        })
+                        .collect();
+                    update_journal(|journal| {
+                        journal.record(operation.describe(entries.len()), entries)
+                    });
+                }
+                sender
+                    .send_blocking(results)
+                    .expect("Error sending file operation results");
+            }
+        ));
+        receiver
+    }
+
+    fn file_operation_finished(
+        &self,
+        operation: &FileOperation,
+        marked: Vec<DuplicateObject>,
+        results: Vec<std::io::Result<PathBuf>>,
+    ) {
+        let duplicates = self.duplicates();
+        let (done, failed) = partition_results(marked, results, |duplicate_object| {
+            duplicate_object.path().into()
+        });
+        for (duplicate_object, _) in &done {
+            if !operation.removes_source() {
+                duplicate_object.set_check(false);
+            } else if let Some(position) = duplicates.find(duplicate_object) {
+                duplicates.remove(position);
+            }
+        }
+
+        self.imp()
+            .scan_status
+            .set_label(&operation_status(operation, done.len(), failed.len()));
+        self.imp()
+            .duplicates_count
+            .set_label(&format_number(duplicates.n_items().into()));
+        self.queue_files_marked_update();
+    }
+
+    /// Replace every marked exact duplicate with a link to an unmarked copy of its
+    /// group, off the main loop.
+    fn link_marked(&self) {
+        let exact = DuplicateKind::Exact.name();
+        let duplicates = self.duplicates();
+        let mut keepers: HashMap<String, PathBuf> = HashMap::new();
+        for duplicate_object in (0..duplicates.n_items())
+            .filter_map(|position| duplicates.item(position).and_downcast::<DuplicateObject>())
+        {
+            if duplicate_object.kind() == exact && !duplicate_object.check() {
+                keepers
+                    .entry(duplicate_object.group())
+                    .or_insert_with(|| duplicate_object.path().into());
+            }
+        }
+
+        let (marked, without_keeper): (Vec<DuplicateObject>, Vec<DuplicateObject>) = self
+            .marked_duplicates()
+            .into_iter()
+            .filter(|duplicate_object| duplicate_object.kind() == exact)
+            .partition(|duplicate_object| keepers.contains_key(&duplicate_object.group()));
+        if marked.is_empty() {
+            if !without_keeper.is_empty() {
+                self.imp()
+                    .scan_status
+                    .set_label("Leave one copy of each group unmarked to link the others to it");
+            }
+            return;
+        }
+        let pairs: Vec<(PathBuf, PathBuf)> = marked
+            .iter()
+            .map(|duplicate_object| {
+                (
+                    duplicate_object.path().into(),
+                    keepers[&duplicate_object.group()].clone(),
+                )
+            })
+            .collect();
+
+        let (sender, receiver) = async_channel::bounded(1);
+        gio::spawn_blocking(move || {
+            let results: Vec<std::io::Result<LinkKind>> = pairs
+                .iter()
+                .map(|(copy, keeper)| replace_with_link(copy, keeper))
+                .collect();
+            let entries: Vec<JournalEntry> = pairs
+                .iter()
+                .zip(&results)
+                .filter(|(_, result)| result.is_ok())
+                .map(|((copy, keeper), _)| {
+                    JournalEntry::new(OperationKind::Replace, copy.clone(), keeper.clone())
+                })
+                .collect();
+            let description = format!("{} duplicates replaced with links", entries.len());
+            update_journal(|journal| journal.record(description, entries));
+            sender
+                .send_blocking(results)
+                .expect("Error sending link results");
+        });
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                if let Ok(results) = receiver.recv().await {
+                    window.links_finished(marked, results, without_keeper.len());
+                }
+            }
+        ));
+    }
+
+    fn links_finished(
+        &self,
+        marked: Vec<DuplicateObject>,
+        results: Vec<std::io::Result<LinkKind>>,
+        without_keeper: usize,
+    ) {
+        let duplicates = self.duplicates();
+        let (linked, failed) = partition_results(marked, results, |duplicate_object| {
+            duplicate_object.path().into()
+        });
+        let (mut reflinks, mut hardlinks, mut symlinks) = (0, 0, 0);
+        let mut reclaimed = 0;
+        for (duplicate_object, kind) in &linked {
+            match kind {
+                LinkKind::Reflink => reflinks += 1,
+                LinkKind::Hardlink => hardlinks += 1,
+                LinkKind::Symlink => symlinks += 1,
+            }
+            reclaimed += duplicate_object
+                .dup_file()
+                .map_or(0, |dup_file| dup_file.file_size);
+            if let Some(position) = duplicates.find(duplicate_object) {
+                duplicates.remove(position);
+            }
+        }
+
+        let space_reclaimed = self.imp().space_reclaimed.get() + reclaimed;
+        self.imp().space_reclaimed.set(space_reclaimed);
+        self.imp()
+            .duplicates_space_reclaimed
+            .set_label(&format_size(space_reclaimed));
+        let mut status = format!(
+            "{} duplicates sharing their data with reflinks, {} replaced with hardlinks and {} with symlinks, {} reclaimed",
+            reflinks,
+            hardlinks,
+            symlinks,
+            format_size(reclaimed)
+        );
+        if !failed.is_empty() {
+            status.push_str(&format!(", {} failed", failed.len()));
+        }
+        if without_keeper > 0 {
+            status.push_str(&format!(
+                ", {} skipped as every copy of their group is marked",
+                without_keeper
+            ));
+        }
+        self.imp().scan_status.set_label(&status);
+        self.imp()
+            .duplicates_count
+            .set_label(&format_number(duplicates.n_items().into()));
+        self.queue_files_marked_update();
+    }
+
+    /// Undo a cleanup off the main loop, the last one that can still be undone if `id` is `None`.
+    fn undo_cleanup(&self, id: Option<u64>) {
+        let (sender, receiver) = async_channel::bounded(1);
+        gio::spawn_blocking(move || {
+            let undone = update_journal(|journal| {
+                let id = id.or_else(|| journal.last_undoable().map(|batch| batch.id))?;
+                let batch = journal.batches().iter().find(|batch| batch.id == id)?;
+                let description = batch.description.clone();
+                Some((description, journal.undo(id)))
+            });
+            sender
+                .send_blocking(undone)
+                .expect("Error sending undo results");
+        });
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                let Ok(undone) = receiver.recv().await else {
+                    return;
+                };
+                let status = match undone {
+                    Some((description, results)) => {
+                        let mut failed = 0;
+                        for error in results.iter().filter_map(|result| result.as_ref().err()) {
+                            eprintln!("Error undoing {}: {}", description, error);
+                            failed += 1;
+                        }
+                        let mut status = format!(
+                            "Undid \"{}\": {} files restored",
+                            description,
+                            results.len() - failed
+                        );
+                        if failed > 0 {
+                            status.push_str(&format!(", {} failed", failed));
+                        }
+                        status
+                    }
+                    None => "Nothing to undo".to_string(),
+                };
+                window.imp().scan_status.set_label(&status);
+                window.display_history();
+            }
+        ));
+    }
+
+    /// List the recorded cleanups, newest first, each with a button to undo it.
+    fn display_history(&self) {
+        let history_list = self.imp().history_list.get();
+        history_list.remove_all();
+
+        let journal = Journal::open();
+        if journal.batches().is_empty() {
+            history_list.append(&gtk::Label::new(Some("No cleanups yet")));
+            return;
+        }
+        for batch in journal.batches().iter().rev() {
+            let mut subtitle = batch
+                .date()
+                .map(|date| date.format("%-m/%-d/%Y %-I:%M:%S %p").to_string())
+                .unwrap_or_default();
+            if batch.is_undone() {
+                subtitle.push_str(", undone");
+            }
+            let restore_button = gtk::Button::builder()
+                .label("Restore")
+                .valign(gtk::Align::Center)
+                .sensitive(batch.can_undo())
+                .build();
+            let id = batch.id;
+            restore_button.connect_clicked(clone!(
+                #[weak(rename_to = window)]
+                self,
+                move |_| window.undo_cleanup(Some(id))
+            ));
+
+            let row = adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&batch.description))
+                .subtitle(subtitle)
+                .build();
+            row.add_suffix(&restore_button);
+            history_list.append(&row);
+        }
+    }
+
+    fn setup_factory(&self) {
+        let factory = SignalListItemFactory::new();
+
+        factory.connect_setup(move |_, list_item| {
+            let expander = TreeExpander::new();
+            expander.set_child(Some(&DuplicateRow::new()));
+            list_item
+                .downcast_ref::<ListItem>()
+                .expect("Needs to be a `ListItem`.")
+                .set_child(Some(&expander));
+        });
+
+        factory.connect_bind(move |_, list_item| {
+            let list_item = list_item
+                .downcast_ref::<ListItem>()
+                .expect("Needs to be a `ListItem`.");
+            let tree_row = list_item
+                .item()
+                .and_downcast::<TreeListRow>()
+                .expect("Needs to be a `TreeListRow`.");
+            let duplicate_object = tree_row
+                .item()
+                .and_downcast::<DuplicateObject>()
+                .expect("Needs to be a `DuplicateObject`.");
+
+            let expander = list_item
+                .child()
+                .and_downcast::<TreeExpander>()
+                .expect("Needs to be a `TreeExpander`.");
+            expander.set_list_row(Some(&tree_row));
+            let duplicate_row = expander
+                .child()
+                .and_downcast::<DuplicateRow>()
+                .expect("Needs to be a `DuplicateRow`.");
+
+            duplicate_row.bind(&duplicate_object);
+        });
+
+        factory.connect_unbind(move |_, list_item| {
+            let expander = list_item
+                .downcast_ref::<ListItem>()
+                .expect("Needs to be a `ListItem`.")
+                .child()
+                .and_downcast::<TreeExpander>()
+                .expect("Needs to be a `TreeExpander`.");
+            expander.set_list_row(None);
+            expander
+                .child()
+                .and_downcast::<DuplicateRow>()
+                .expect("Needs to be a `DuplicateRow`.")
+                .unbind();
+        });
+
+        self.imp().duplicates_list.set_factory(Some(&factory));
+    }
+
+    fn filters(&self) -> Option<CustomFilter> {
+        let filter_modes = self.imp().filter_modes.borrow().clone();
+
+        // if `filter_modes` is just empty, return `None`
+        if filter_modes.is_empty() {
+            return None;
+        }
+
+        let filter = CustomFilter::new(move |obj| {
+            let duplicate_object = obj
+                .downcast_ref::<DuplicateObject>()
+                .expect("Not a DuplicateObject");
+            // only allow duplicates that are of the same type as those in the filter_modes
+            filter_modes.contains(&duplicate_object.filter_mode())
+        });
+
+        Some(filter)
+    }
+
+    fn apply_filters(&self) {
+        let selection_mode = self.duplicates_model();
+        self.imp().duplicates_list.set_model(Some(&selection_mode));
+
+        let duplicates_count = selection_mode.n_items();
+        self.imp()
+            .duplicates_count
+            .set_label(&format_number(duplicates_count.into()));
+
+        self.imp()
+            .duplicates_space_taken
+            .set_label(&format_size(self.filtered_reclaimable_size()));
+    }
+
+    /// Space freed by removing the exact duplicates that pass the filters, leaving
+    /// out the copy the keep rules choose to keep in each group.
+    fn filtered_reclaimable_size(&self) -> u64 {
+        let filter = self.filters();
+        let duplicates = self.duplicates();
+        let mut groups: HashMap<String, Vec<(bool, DupFile)>> = HashMap::new();
+        for position in 0..duplicates.n_items() {
+            let Some(duplicate_object) =
+                duplicates.item(position).and_downcast::<DuplicateObject>()
+            else {
+                continue;
+            };
+            if duplicate_object.kind() != DuplicateKind::Exact.name() {
+                continue;
+            }
+            let Some(dup_file) = duplicate_object.dup_file() else {
+                continue;
+            };
+            let shown = filter
+                .as_ref()
+                .is_none_or(|filter| filter.match_(&duplicate_object));
+            groups
+                .entry(duplicate_object.group())
+                .or_default()
+                .push((shown, dup_file));
+        }
+
+        let rules = self.keep_rules();
+        groups
+            .into_values()
+            .map(|group| {
+                let files: Vec<DupFile> = group.iter().map(|(_, file)| file.clone()).collect();
+                let keeper = choose_keeper(&files, &rules);
+                group
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(position, (shown, _))| *shown && Some(*position) != keeper)
+                    .map(|(_, (_, file))| file.file_size)
+                    .sum::<u64>()
+            })
+            .sum()
+    }
+
+    fn show_preview(&self) {
+        self.imp().duplicates_list.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |list_view, i| {
+                // the position is in the filtered and sorted model, not the store
+                let Some(tree_row) = list_view
+                    .model()
+                    .and_then(|model| model.item(i))
+                    .and_downcast::<TreeListRow>()
+                else {
+                    return;
+                };
+                if let Some(item) = tree_row.item() {
+                    let duplicate_object = item
+                        .downcast_ref::<DuplicateObject>()
+                        .expect("Not a DuplicateObject");
+                    if duplicate_object.is_directory() {
+                        tree_row.set_expanded(!tree_row.is_expanded());
+                        return;
+                    }
+                    let path = duplicate_object.path();
+                    let preview = Preview::new(path.into());
+                    if let Some(widget) = preview.widget() {
+                        window.imp().preview_viewport.set_child(Some(&widget));
+                    }
+                }
+            }
+        ));
+    }
+}
+
+/// Split `items` into the ones an operation succeeded for, with what it
+/// returned, and the ones it failed for, printing why. `path` names the file of
+/// an item.
+fn partition_results<I, T>(
+    items: Vec<I>,
+    results: Vec<std::io::Result<T>>,
+    path: impl Fn(&I) -> PathBuf,
+) -> (Vec<(I, T)>, Vec<I>) {
+    let mut done = Vec::new();
+    let mut failed = Vec::new();
+    for (item, result) in items.into_iter().zip(results) {
+        match result {
+            Ok(value) => done.push((item, value)),
+            Err(e) => {
+                eprintln!("Error handling {:?}: {}", path(&item), e);
+                failed.push(item);
+            }
+        }
+    }
+    (done, failed)
+}
+
+/// How many files an operation handled, and how many it failed for if any.
+fn operation_status(operation: &FileOperation, done: usize, failed: usize) -> String {
+    let mut status = operation.describe(done);
+    if failed > 0 {
+        status.push_str(&format!(", {} failed", failed));
+    }
+    status
+}
+
+/// The heading of a day of the timeline.
+fn day_label(day: NaiveDate, today: NaiveDate) -> String {
+    if day == today {
+        "Today".to_string()
+    } else if today.pred_opt() == Some(day) {
+        "Yesterday".to_string()
+    } else {
+        day.format("%A %-d %B").to_string()
+    }
+}
+
+/// An action, as displayed on the Recents panel.
+fn action_text(action: &Action, node: Node, path: &Path) -> String {
+    let entry = |path: &Path| match node {
+        Node::File => format!("the file {}", entry_name(path)),
+        Node::Folder => format!("the folder {}", entry_name(path)),
+        Node::Unknown => entry_name(path),
+    };
+    match action {
+        Action::Create { moved: false } => format!("Created {}", entry(path)),
+        Action::Create { moved: true } => format!("Moved in {}", entry(path)),
+        Action::Remove { moved: false } => format!("Removed {}", entry(path)),
+        Action::Remove { moved: true } => format!("Moved away {}", entry(path)),
+        Action::Access => format!("Opened {}", entry(path)),
+        Action::Rename { from, to } => {
+            format!("Renamed {} to {}", entry(from), entry_name(to))
+        }
+        Action::Move { from, to } => format!(
+            "Moved {} to {}",
+            entry(from),
+            to.parent().unwrap_or(to).display()
+        ),
+        Action::Modify(change) => {
+            let what = match change {
+                Change::Content => "content",
+                Change::Size => "size",
+                Change::Permissions => "permissions",
+                Change::Ownership => "owner",
+                Change::WriteTime => "modification time",
+                Change::AccessTime => "access time",
+                Change::Extended => "extended attributes",
+                Change::Name => return format!("Renamed {}", entry(path)),
+                Change::Moved | Change::Any => return format!("Modified {}", entry(path)),
+            };
+            format!("Changed the {} of {}", what, entry(path))
+        }
+    }
+}
+
+fn action_kind_label(kind: ActionKind) -> &'static str {
+    match kind {
+        ActionKind::Create => "Created",
+        ActionKind::Remove => "Removed",
+        ActionKind::Modify => "Modified",
+        ActionKind::Access => "Opened",
+        ActionKind::Rename => "Renamed",
+        ActionKind::Move => "Moved",
+    }
+}
+
+fn entry_name(path: &Path) -> String {
+    path.file_name().map_or(path.display().to_string(), |name| {
+        name.to_string_lossy().to_string()
+    })
 }